@@ -5,7 +5,7 @@ use std::{borrow::Cow, process::Command};
 
 pub fn generate_cargo_keys() {
     let output = Command::new("git")
-        .args(&["rev-parse", "--short", "HEAD"])
+        .args(["rev-parse", "--short", "HEAD"])
         .output();
 
     let commit = match output {
@@ -1,9 +1,17 @@
 use core::fmt;
 
+use serde::Serialize;
 use tracing::{event, instrument, Level};
 use warp::{
-    body::BodyDeserializeError, cors::CorsForbidden, hyper::StatusCode,
-    reject::Reject, Rejection, Reply,
+    body::BodyDeserializeError,
+    cors::CorsForbidden,
+    http::{
+        header::{RETRY_AFTER, WWW_AUTHENTICATE},
+        HeaderValue,
+    },
+    hyper::StatusCode,
+    reject::Reject,
+    Rejection, Reply,
 };
 
 use reqwest::Error as ReqwestError;
@@ -14,9 +22,18 @@ use argon2::Error as ArgonError;
 pub enum Error {
     ParseError(std::num::ParseIntError),
     MissingParameters,
+    InvalidParameter(String),
+    UnknownParameter(String),
+    InvalidTags(String),
+    ValidationFailed(Vec<FieldError>),
     IndexOutOfBound,
     WrongPassword,
     CannotDecryptToken,
+    InvalidToken,
+    TooManyRequests(u64),
+    RateLimited { limit: u32, reset: u64, retry_after: u64 },
+    EmailNotVerified,
+    MailError(String),
     ArgonLibraryError(ArgonError),
     DatabaseQueryError(sqlx::Error),
     ReqwestAPIError(ReqwestError),
@@ -24,7 +41,41 @@ pub enum Error {
     ClientError(APILayerError),
     ServerError(APILayerError),
     Unauthorized,
+    Forbidden,
     MigrationError(sqlx::migrate::MigrateError),
+    QuestionNotFound,
+    QuestionGone,
+    AccountNotFound,
+    AnswerNotFound,
+    CommentNotFound,
+    RevisionNotFound,
+    TagNotFound,
+    CannotVoteOwnPost,
+    AnswerOfOtherQuestion,
+    PreconditionFailed,
+}
+
+/// A rejected field of a request body, returned to the client as part of
+/// a `400` response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Body of the response to `ValidationFailed`
+#[derive(Serialize)]
+struct ValidationErrors<'a> {
+    errors: &'a [FieldError],
 }
 
 #[derive(Debug, Clone)]
@@ -46,6 +97,22 @@ impl fmt::Display for Error {
                 write!(f, "Cannot parse parameter: {}", e)
             }
             Error::MissingParameters => write!(f, "Missing parameter(s)"),
+            Error::InvalidParameter(ref param) => {
+                write!(f, "Invalid parameter: {}", param)
+            }
+            Error::UnknownParameter(ref param) => {
+                write!(f, "Unknown parameter: {}", param)
+            }
+            Error::InvalidTags(ref reason) => {
+                write!(f, "Invalid tags: {}", reason)
+            }
+            Error::ValidationFailed(ref errors) => {
+                write!(f, "Validation failed:")?;
+                for error in errors {
+                    write!(f, " {}: {};", error.field, error.message)?;
+                }
+                Ok(())
+            }
             Error::IndexOutOfBound => write!(f, "Index out of bound"),
             Error::DatabaseQueryError(_) => {
                 write!(f, "Cannot update, invalid data.")
@@ -71,12 +138,60 @@ impl fmt::Display for Error {
             Error::CannotDecryptToken => {
                 write!(f, "Cannot decrypt auth token")
             },
+            Error::InvalidToken => {
+                write!(f, "Invalid or expired token")
+            },
+            Error::TooManyRequests(retry_after) => {
+                write!(f, "Too many requests, retry in {} seconds", retry_after)
+            },
+            Error::RateLimited { retry_after, .. } => {
+                write!(f, "Rate limit exceeded, retry in {} seconds", retry_after)
+            },
+            Error::EmailNotVerified => {
+                write!(f, "Email address has not been verified yet")
+            },
+            Error::MailError(ref err) => {
+                write!(f, "Cannot send email: {}", err)
+            },
             Error::Unauthorized => {
+                write!(f, "Missing or invalid authentication")
+            },
+            Error::Forbidden => {
                 write!(f, "No permission to change the underlying resource")
             },
             Error::MigrationError(_) => {
                 write!(f, "Cannot migrate data")
             },
+            Error::QuestionNotFound => {
+                write!(f, "Question not found")
+            },
+            Error::QuestionGone => {
+                write!(f, "Question has been deleted")
+            },
+            Error::AccountNotFound => {
+                write!(f, "Account not found")
+            },
+            Error::AnswerNotFound => {
+                write!(f, "Answer not found")
+            },
+            Error::CommentNotFound => {
+                write!(f, "Comment not found")
+            },
+            Error::RevisionNotFound => {
+                write!(f, "Revision not found")
+            },
+            Error::TagNotFound => {
+                write!(f, "Tag not found")
+            },
+            Error::CannotVoteOwnPost => {
+                write!(f, "Cannot vote on your own post")
+            },
+            Error::AnswerOfOtherQuestion => {
+                write!(f, "Answer belongs to another question")
+            },
+            Error::PreconditionFailed => {
+                write!(f, "Resource has been modified in the meantime")
+            },
         }
     }
 }
@@ -85,8 +200,50 @@ impl Reject for Error {}
 impl Reject for APILayerError {}
 
 const DUPLICATE_KEY_ERRORCODE: u32 = 23505;
+/// Turn a rejection into an error response. A `401` tells the client
+/// how to authenticate via `WWW-Authenticate`, a `429` when to retry via
+/// `Retry-After` and, when a rate limit was hit, the `RateLimit-*` headers.
+/// A failed validation answers with a `400` listing the rejected fields
+/// as JSON
 #[instrument]
 pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
+    if let Some(crate::Error::ValidationFailed(errors)) = r.find() {
+        event!(Level::WARN, "Validation failed: {:?}", errors);
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&ValidationErrors { errors }),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response());
+    }
+    let unauthorized = matches!(r.find(), Some(crate::Error::Unauthorized));
+    let retry_after = match r.find() {
+        Some(crate::Error::TooManyRequests(retry_after)) => Some(*retry_after),
+        Some(crate::Error::RateLimited { retry_after, .. }) => Some(*retry_after),
+        _ => None,
+    };
+    let rate_limit = match r.find() {
+        Some(crate::Error::RateLimited { limit, reset, .. }) => Some((*limit, *reset)),
+        _ => None,
+    };
+    let mut response = error_reply(r).await?.into_response();
+    if unauthorized {
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    if let Some(retry_after) = retry_after {
+        response.headers_mut().insert(RETRY_AFTER, retry_after.into());
+    }
+    if let Some((limit, reset)) = rate_limit {
+        let headers = response.headers_mut();
+        headers.insert("ratelimit-limit", limit.into());
+        headers.insert("ratelimit-remaining", 0.into());
+        headers.insert("ratelimit-reset", reset.into());
+    }
+    Ok(response)
+}
+
+async fn error_reply(r: Rejection) -> Result<impl Reply, Rejection> {
     if let Some(crate::Error::DatabaseQueryError(e)) = r.find() {
         event!(Level::ERROR, "Database query error");
         match e {
@@ -124,11 +281,110 @@ pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
             StatusCode::UNAUTHORIZED,
         ))
     } else if let Some(crate::Error::Unauthorized) = r.find() {
-        event!(Level::ERROR, "Not matching account id");
+        event!(Level::WARN, "Missing or invalid authentication");
         Ok(warp::reply::with_status(
-            "No permitted to change underlying resource".to_string(),
+            "Missing or invalid authentication".to_string(),
             StatusCode::UNAUTHORIZED,
         ))
+    } else if let Some(
+        e @ (crate::Error::TooManyRequests(_) | crate::Error::RateLimited { .. }),
+    ) = r.find()
+    {
+        event!(Level::WARN, "{}", e);
+        Ok(warp::reply::with_status(
+            e.to_string(),
+            StatusCode::TOO_MANY_REQUESTS,
+        ))
+    } else if let Some(crate::Error::EmailNotVerified) = r.find() {
+        event!(Level::WARN, "Account has not verified its email address");
+        Ok(warp::reply::with_status(
+            "Email address has not been verified yet".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(crate::Error::MailError(e)) = r.find() {
+        event!(Level::ERROR, "{}", e);
+        Ok(warp::reply::with_status(
+            "Internal Server Error".to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))
+    } else if let Some(crate::Error::Forbidden) = r.find() {
+        event!(Level::ERROR, "Not matching account id or role");
+        Ok(warp::reply::with_status(
+            "No permitted to change underlying resource".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(
+        e @ (crate::Error::InvalidParameter(_)
+        | crate::Error::UnknownParameter(_)
+        | crate::Error::InvalidTags(_)
+        | crate::Error::InvalidToken),
+    ) = r.find()
+    {
+        event!(Level::WARN, "{}", e);
+        Ok(warp::reply::with_status(
+            e.to_string(),
+            StatusCode::BAD_REQUEST,
+        ))
+    } else if let Some(crate::Error::QuestionNotFound) = r.find() {
+        event!(Level::WARN, "Requested question was not found");
+        Ok(warp::reply::with_status(
+            "Question not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::QuestionGone) = r.find() {
+        event!(Level::WARN, "Requested question has been deleted");
+        Ok(warp::reply::with_status(
+            "Question has been deleted".to_string(),
+            StatusCode::GONE,
+        ))
+    } else if let Some(crate::Error::AccountNotFound) = r.find() {
+        event!(Level::WARN, "Requested account was not found");
+        Ok(warp::reply::with_status(
+            "Account not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::AnswerNotFound) = r.find() {
+        event!(Level::WARN, "Requested answer was not found");
+        Ok(warp::reply::with_status(
+            "Answer not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::CommentNotFound) = r.find() {
+        event!(Level::WARN, "Requested comment was not found");
+        Ok(warp::reply::with_status(
+            "Comment not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::RevisionNotFound) = r.find() {
+        event!(Level::WARN, "Requested revision was not found");
+        Ok(warp::reply::with_status(
+            "Revision not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::TagNotFound) = r.find() {
+        event!(Level::WARN, "Requested tag was not found");
+        Ok(warp::reply::with_status(
+            "Tag not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::CannotVoteOwnPost) = r.find() {
+        event!(Level::WARN, "Tried to vote on own post");
+        Ok(warp::reply::with_status(
+            "Cannot vote on your own post".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(crate::Error::AnswerOfOtherQuestion) = r.find() {
+        event!(Level::WARN, "Answer belongs to another question");
+        Ok(warp::reply::with_status(
+            "Answer belongs to another question".to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ))
+    } else if let Some(crate::Error::PreconditionFailed) = r.find() {
+        event!(Level::WARN, "If-Match precondition failed");
+        Ok(warp::reply::with_status(
+            "Resource has been modified in the meantime".to_string(),
+            StatusCode::PRECONDITION_FAILED,
+        ))
     } else if let Some(crate::Error::MiddlewareReqwestAPIError(e)) =
         r.find()
     {
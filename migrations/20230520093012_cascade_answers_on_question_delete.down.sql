@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE answers
+DROP CONSTRAINT IF EXISTS answers_corresponding_question_fkey,
+ADD CONSTRAINT answers_corresponding_question_fkey
+    FOREIGN KEY (corresponding_question) REFERENCES questions (id);
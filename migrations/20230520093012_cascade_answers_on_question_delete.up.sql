@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE answers
+DROP CONSTRAINT IF EXISTS answers_corresponding_question_fkey,
+ADD CONSTRAINT answers_corresponding_question_fkey
+    FOREIGN KEY (corresponding_question) REFERENCES questions (id)
+    ON DELETE CASCADE;
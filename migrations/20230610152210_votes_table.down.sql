@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS questions_score_idx;
+
+ALTER TABLE answers
+DROP COLUMN IF EXISTS score;
+
+ALTER TABLE questions
+DROP COLUMN IF EXISTS score;
+
+DROP TABLE IF EXISTS votes;
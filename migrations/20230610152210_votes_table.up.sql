@@ -0,0 +1,21 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS votes (
+    id serial PRIMARY KEY,
+    account_id integer NOT NULL,
+    question_id integer REFERENCES questions ON DELETE CASCADE,
+    answer_id integer REFERENCES answers ON DELETE CASCADE,
+    value smallint NOT NULL CHECK (value IN (-1, 1)),
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK (num_nonnulls(question_id, answer_id) = 1),
+    UNIQUE (account_id, question_id),
+    UNIQUE (account_id, answer_id)
+);
+
+ALTER TABLE questions
+ADD COLUMN score integer NOT NULL DEFAULT 0;
+
+ALTER TABLE answers
+ADD COLUMN score integer NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS questions_score_idx
+ON questions (score DESC, created_on DESC, id DESC);
@@ -0,0 +1,29 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS tags (
+    name TEXT PRIMARY KEY,
+    description TEXT,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Alternative spellings which are replaced by their tag on write
+CREATE TABLE IF NOT EXISTS tag_synonyms (
+    synonym TEXT PRIMARY KEY,
+    tag_name TEXT NOT NULL REFERENCES tags ON DELETE CASCADE,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Normalize the existing free-form tags: trimmed, lowercase and unique
+UPDATE questions
+SET tags = (
+    SELECT coalesce(array_agg(DISTINCT lower(trim(tag))), '{}')
+    FROM unnest(questions.tags) AS tag
+    WHERE trim(tag) <> ''
+)
+WHERE tags IS NOT NULL;
+
+INSERT INTO tags (name)
+SELECT DISTINCT unnest(tags) FROM questions
+ON CONFLICT DO NOTHING;
+
+CREATE INDEX IF NOT EXISTS questions_tags_idx
+ON questions USING GIN (tags);
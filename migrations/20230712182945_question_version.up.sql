@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE questions ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
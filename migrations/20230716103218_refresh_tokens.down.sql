@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE accounts
+DROP COLUMN tokens_valid_after;
+
+DROP TABLE IF EXISTS revoked_tokens;
+DROP TABLE IF EXISTS refresh_tokens;
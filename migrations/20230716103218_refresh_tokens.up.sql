@@ -0,0 +1,24 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    id serial PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    session_id TEXT NOT NULL,
+    account_id integer NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_session_id_idx
+ON refresh_tokens (session_id);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_account_id_idx
+ON refresh_tokens (account_id);
+
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+    jti TEXT PRIMARY KEY,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+ALTER TABLE accounts
+ADD COLUMN tokens_valid_after TIMESTAMPTZ;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE accounts
+ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT false;
+
+-- Accounts created before verification existed stay usable
+UPDATE accounts SET email_verified = true;
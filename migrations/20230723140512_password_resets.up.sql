@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS password_resets (
+    id serial PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    account_id integer NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE accounts
+DROP COLUMN deleted_at;
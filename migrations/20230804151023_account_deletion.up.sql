@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE accounts
+ADD COLUMN deleted_at TIMESTAMPTZ;
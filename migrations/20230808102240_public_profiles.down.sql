@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE accounts
+DROP COLUMN display_name,
+DROP COLUMN created_on;
@@ -0,0 +1,12 @@
+-- Add up migration script here
+ALTER TABLE accounts
+ADD COLUMN display_name VARCHAR(64),
+ADD COLUMN created_on TIMESTAMP NOT NULL DEFAULT NOW();
+
+-- Existing accounts joined no later than their first post
+UPDATE accounts a
+SET created_on = least(
+    a.created_on,
+    (SELECT min(created_on) FROM questions WHERE account_id = a.id),
+    (SELECT min(created_on) FROM answers WHERE account_id = a.id)
+);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS rate_limits_full_at_idx;
+
+ALTER TABLE rate_limits
+DROP COLUMN full_at;
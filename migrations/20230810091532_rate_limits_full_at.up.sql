@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE rate_limits
+ADD COLUMN full_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX IF NOT EXISTS rate_limits_full_at_idx
+ON rate_limits (full_at);
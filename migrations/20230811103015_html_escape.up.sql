@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- Escapes text for HTML, used for the search snippets which are
+-- highlighted with <mark> tags
+CREATE OR REPLACE FUNCTION html_escape(input TEXT) RETURNS TEXT
+LANGUAGE sql IMMUTABLE STRICT
+AS $$
+    SELECT replace(replace(replace(replace(replace(input,
+        '&', '&amp;'),
+        '<', '&lt;'),
+        '>', '&gt;'),
+        '"', '&quot;'),
+        '''', '&#39;')
+$$;
@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS login_attempts_last_failure_idx;
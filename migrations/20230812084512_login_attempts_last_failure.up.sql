@@ -0,0 +1,3 @@
+-- Add up migration script here
+CREATE INDEX IF NOT EXISTS login_attempts_last_failure_idx
+ON login_attempts (last_failure);
@@ -1,5 +1,5 @@
 use clap::Parser;
-use std::env;
+use std::{env, str::FromStr};
 
 /// Q&A web service API
 #[derive(Debug, Parser, PartialEq)]
@@ -26,6 +26,98 @@ pub struct Config {
     /// Database name
     #[clap(long, default_value = "rustwebdev")]
     pub db_name: String,
+    /// Also accept the token in the legacy `auth` header next to
+    /// `Authorization: Bearer`
+    #[clap(long)]
+    pub legacy_auth_header: bool,
+    /// How emails are delivered (smtp or file)
+    #[clap(long, default_value = "file")]
+    pub mailer: String,
+    /// Host of the SMTP relay
+    #[clap(long, default_value = "localhost")]
+    pub smtp_host: String,
+    /// PORT of the SMTP relay
+    #[clap(long, default_value = "587")]
+    pub smtp_port: u16,
+    /// (Optional) User for the SMTP relay
+    #[clap(long)]
+    pub smtp_user: Option<String>,
+    /// (Optional) Password for the SMTP relay
+    #[clap(long)]
+    pub smtp_password: Option<String>,
+    /// Sender address of the emails
+    #[clap(long, default_value = "Q&A <noreply@localhost>")]
+    pub mail_from: String,
+    /// Directory the file mailer writes the emails to
+    #[clap(long, default_value = "mails")]
+    pub mail_dir: String,
+    /// URL the service is reachable at, used for links in emails
+    #[clap(long, default_value = "http://localhost:8080")]
+    pub public_url: String,
+    /// Failed logins per email or client IP before it gets locked out
+    #[clap(long, default_value = "5")]
+    pub login_max_failures: u32,
+    /// Seconds of the first lockout, doubled with every further failure
+    #[clap(long, default_value = "30")]
+    pub login_lockout_seconds: u64,
+    /// Upper bound for the lockout in seconds
+    #[clap(long, default_value = "3600")]
+    pub login_max_lockout_seconds: u64,
+    /// Take the client IP from the last `X-Forwarded-For` entry, only
+    /// enable this behind a proxy which sets the header
+    #[clap(long)]
+    pub trust_forwarded_for: bool,
+    /// Where rate limit buckets are kept (memory or postgres)
+    #[clap(long, default_value = "memory")]
+    pub rate_limit_backend: String,
+    /// Requests per minute and client IP over all routes, 0 disables it
+    #[clap(long, default_value = "600")]
+    pub rate_limit_global: u32,
+    /// Reading requests per minute and client IP
+    #[clap(long, default_value = "300")]
+    pub rate_limit_reads: u32,
+    /// Writing requests per minute and account
+    #[clap(long, default_value = "30")]
+    pub rate_limit_writes: u32,
+    /// Requests to the authentication endpoints per minute and client IP
+    #[clap(long, default_value = "10")]
+    pub rate_limit_auth: u32,
+}
+
+/// Lockout policy for failed logins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoginLimits {
+    pub max_failures: u32,
+    pub lockout_seconds: u64,
+    pub max_lockout_seconds: u64,
+}
+
+impl LoginLimits {
+    /// Seconds to lock out after `failures` failed logins in a row, with
+    /// exponential backoff once `max_failures` is reached
+    /// # Example usage
+    /// ```rust
+    /// use rustwebdev::config::LoginLimits;
+    ///
+    /// let limits = LoginLimits {
+    ///     max_failures: 5,
+    ///     lockout_seconds: 30,
+    ///     max_lockout_seconds: 3600,
+    /// };
+    /// assert_eq!(limits.lockout(4), None);
+    /// assert_eq!(limits.lockout(5), Some(30));
+    /// assert_eq!(limits.lockout(7), Some(120));
+    /// assert_eq!(limits.lockout(20), Some(3600));
+    /// ```
+    pub fn lockout(&self, failures: u32) -> Option<u64> {
+        let exponent = failures.checked_sub(self.max_failures)?;
+        let factor = 2u64.checked_pow(exponent).unwrap_or(u64::MAX);
+        Some(
+            self.lockout_seconds
+                .saturating_mul(factor)
+                .min(self.max_lockout_seconds),
+        )
+    }
 }
 
 impl Config {
@@ -34,11 +126,11 @@ impl Config {
 
         let config = Config::parse();
 
-        if let Err(_) = env::var("BAD_WORDS_API_KEY") {
+        if env::var("BAD_WORDS_API_KEY").is_err() {
             panic!("BadWords API key not set");
         }
 
-        if let Err(_) = env::var("PASETO_KEY") {
+        if env::var("PASETO_KEY").is_err() {
             panic!("PASETO key not set");
         }
 
@@ -46,7 +138,7 @@ impl Config {
             .ok()
             .map(|val| val.parse::<u16>())
             .unwrap_or(Ok(8080))
-            .map_err(|e| handle_errors::Error::ParseError(e))?;
+            .map_err(handle_errors::Error::ParseError)?;
 
         let db_user =
             env::var("POSTGRES_USER").unwrap_or(config.db_user.to_owned());
@@ -57,6 +149,43 @@ impl Config {
             .unwrap_or(config.db_port.to_string());
         let db_name =
             env::var("POSTGRES_DB").unwrap_or(config.db_name.to_owned());
+        let legacy_auth_header =
+            env_or("LEGACY_AUTH_HEADER", config.legacy_auth_header)?;
+        let mailer =
+            env::var("MAILER").unwrap_or(config.mailer.to_owned());
+        let smtp_host =
+            env::var("SMTP_HOST").unwrap_or(config.smtp_host.to_owned());
+        let smtp_port =
+            env::var("SMTP_PORT").unwrap_or(config.smtp_port.to_string());
+        let smtp_user = env::var("SMTP_USER").ok().or(config.smtp_user);
+        let smtp_password =
+            env::var("SMTP_PASSWORD").ok().or(config.smtp_password);
+        let mail_from =
+            env::var("MAIL_FROM").unwrap_or(config.mail_from.to_owned());
+        let mail_dir =
+            env::var("MAIL_DIR").unwrap_or(config.mail_dir.to_owned());
+        let public_url =
+            env::var("PUBLIC_URL").unwrap_or(config.public_url.to_owned());
+        let login_max_failures =
+            env_or("LOGIN_MAX_FAILURES", config.login_max_failures)?;
+        let login_lockout_seconds =
+            env_or("LOGIN_LOCKOUT_SECONDS", config.login_lockout_seconds)?;
+        let login_max_lockout_seconds = env_or(
+            "LOGIN_MAX_LOCKOUT_SECONDS",
+            config.login_max_lockout_seconds,
+        )?;
+        let trust_forwarded_for =
+            env_or("TRUST_FORWARDED_FOR", config.trust_forwarded_for)?;
+        let rate_limit_backend = env::var("RATE_LIMIT_BACKEND")
+            .unwrap_or(config.rate_limit_backend.to_owned());
+        let rate_limit_global =
+            env_or("RATE_LIMIT_GLOBAL", config.rate_limit_global)?;
+        let rate_limit_reads =
+            env_or("RATE_LIMIT_READS", config.rate_limit_reads)?;
+        let rate_limit_writes =
+            env_or("RATE_LIMIT_WRITES", config.rate_limit_writes)?;
+        let rate_limit_auth =
+            env_or("RATE_LIMIT_AUTH", config.rate_limit_auth)?;
         Ok(Config {
             log_level: config.log_level,
             port,
@@ -65,17 +194,61 @@ impl Config {
             db_host,
             db_port: db_port
                 .parse::<u16>()
-                .map_err(|e| handle_errors::Error::ParseError(e))?,
+                .map_err(handle_errors::Error::ParseError)?,
             db_name,
+            legacy_auth_header,
+            mailer,
+            smtp_host,
+            smtp_port: smtp_port
+                .parse::<u16>()
+                .map_err(handle_errors::Error::ParseError)?,
+            smtp_user,
+            smtp_password,
+            mail_from,
+            mail_dir,
+            public_url,
+            login_max_failures,
+            login_lockout_seconds,
+            login_max_lockout_seconds,
+            trust_forwarded_for,
+            rate_limit_backend,
+            rate_limit_global,
+            rate_limit_reads,
+            rate_limit_writes,
+            rate_limit_auth,
         })
     }
+
+    pub fn login_limits(&self) -> LoginLimits {
+        LoginLimits {
+            max_failures: self.login_max_failures,
+            lockout_seconds: self.login_lockout_seconds,
+            max_lockout_seconds: self.login_max_lockout_seconds,
+        }
+    }
+}
+
+/// Read `key` from the environment, falling back to `default` if unset
+fn env_or<T: FromStr>(
+    key: &str,
+    default: T,
+) -> Result<T, handle_errors::Error> {
+    match env::var(key) {
+        Ok(val) => val.parse::<T>().map_err(|_| {
+            handle_errors::Error::InvalidParameter(format!(
+                "{}={}",
+                key, val
+            ))
+        }),
+        Err(_) => Ok(default),
+    }
 }
 #[cfg(test)]
 mod config_tests {
     use super::*;
     fn set_env() {
         env::set_var("BAD_WORDS_API_KEY", "yes");
-        env::set_var("PASETO_KEY", "yes");
+        env::set_var("PASETO_KEY", "RANDOM WORDS WINTER MACINTOSH PC");
         env::set_var("POSTGRES_USER", "user");
         env::set_var("POSTGRES_PASSWORD", "password");
         env::set_var("POSTGRES_HOST", "localhost");
@@ -84,7 +257,7 @@ mod config_tests {
     }
     #[test]
     fn unset_api_key() {
-        let result = std::panic::catch_unwind(|| Config::new());
+        let result = std::panic::catch_unwind(Config::new);
         assert!(result.is_err());
 
         set_env();
@@ -96,6 +269,24 @@ mod config_tests {
             db_host: "localhost".to_string(),
             db_port: 5432,
             db_name: "rustwebdev".to_string(),
+            legacy_auth_header: false,
+            mailer: "file".to_string(),
+            smtp_host: "localhost".to_string(),
+            smtp_port: 587,
+            smtp_user: None,
+            smtp_password: None,
+            mail_from: "Q&A <noreply@localhost>".to_string(),
+            mail_dir: "mails".to_string(),
+            public_url: "http://localhost:8080".to_string(),
+            login_max_failures: 5,
+            login_lockout_seconds: 30,
+            login_max_lockout_seconds: 3600,
+            trust_forwarded_for: false,
+            rate_limit_backend: "memory".to_string(),
+            rate_limit_global: 600,
+            rate_limit_reads: 300,
+            rate_limit_writes: 30,
+            rate_limit_auth: 10,
         };
         let config = Config::new().unwrap();
         assert_eq!(config, expected);
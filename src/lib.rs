@@ -21,4 +21,10 @@ pub mod types;
 
 pub mod profanity;
 
-pub mod config;
\ No newline at end of file
+pub mod config;
+
+/// Delivery of the emails sent by the service
+pub mod mailer;
+
+/// Token bucket rate limits for groups of routes
+pub mod rate_limit;
@@ -0,0 +1,231 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use handle_errors::Error;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::config::Config;
+
+/// A plain text email
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers emails, implemented for SMTP and for local files
+#[async_trait]
+pub trait Mailer: fmt::Debug + Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), Error>;
+}
+
+/// Sends emails through an SMTP relay using STARTTLS
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, Error> {
+        let mut transport =
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .map_err(|e| Error::MailError(e.to_string()))?
+                .port(port);
+        if let Some((user, password)) = credentials {
+            transport =
+                transport.credentials(Credentials::new(user, password));
+        }
+
+        Ok(SmtpMailer {
+            transport: transport.build(),
+            from: from.parse().map_err(|_| {
+                Error::InvalidParameter(format!("mail_from={}", from))
+            })?,
+        })
+    }
+}
+
+impl fmt::Debug for SmtpMailer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmtpMailer")
+            .field("from", &self.from)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), Error> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse().map_err(
+                |e: lettre::address::AddressError| {
+                    Error::MailError(e.to_string())
+                },
+            )?)
+            .subject(email.subject)
+            .body(email.body)
+            .map_err(|e| Error::MailError(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| Error::MailError(e.to_string()))
+    }
+}
+
+/// Writes every email into its own file in `dir` and logs it, for local
+/// development and tests
+#[derive(Debug)]
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileMailer { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| Error::MailError(e.to_string()))?;
+        let path = self.dir.join(format!(
+            "{}-{}.txt",
+            chrono::Utc::now().format("%Y%m%d%H%M%S"),
+            uuid::Uuid::new_v4()
+        ));
+        tracing::info!(
+            "writing email to {} into {}",
+            email.to,
+            path.display()
+        );
+
+        tokio::fs::write(
+            &path,
+            format!(
+                "To: {}\nSubject: {}\n\n{}\n",
+                email.to, email.subject, email.body
+            ),
+        )
+        .await
+        .map_err(|e| Error::MailError(e.to_string()))
+    }
+}
+
+/// Create the mailer selected by `config.mailer`
+pub fn from_config(config: &Config) -> Result<Arc<dyn Mailer>, Error> {
+    match config.mailer.as_str() {
+        "smtp" => Ok(Arc::new(SmtpMailer::new(
+            &config.smtp_host,
+            config.smtp_port,
+            config.smtp_user.clone().zip(config.smtp_password.clone()),
+            &config.mail_from,
+        )?)),
+        "file" => Ok(Arc::new(FileMailer::new(&config.mail_dir))),
+        mailer => {
+            Err(Error::InvalidParameter(format!("mailer={}", mailer)))
+        }
+    }
+}
+
+/// Composes the emails sent by the service, links point to `public_url`
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    mailer: Arc<dyn Mailer>,
+    public_url: String,
+}
+
+impl Notifier {
+    pub fn new(mailer: Arc<dyn Mailer>, public_url: &str) -> Self {
+        Notifier {
+            mailer,
+            public_url: public_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Ask the owner of `to` to confirm the address of a new account
+    pub async fn send_verification(
+        &self,
+        to: &str,
+        token: &str,
+    ) -> Result<(), Error> {
+        self.mailer
+            .send(Email {
+                to: to.to_string(),
+                subject: "Please verify your email address".to_string(),
+                body: format!(
+                    "Welcome! Open the following link to verify your \
+                    email address:\n\n{}/verify-email?token={}\n",
+                    self.public_url, token
+                ),
+            })
+            .await
+    }
+
+    /// Send a password reset link to `to`
+    pub async fn send_password_reset(
+        &self,
+        to: &str,
+        token: &str,
+    ) -> Result<(), Error> {
+        self.mailer
+            .send(Email {
+                to: to.to_string(),
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Somebody asked to reset the password of your account. \
+                    Open the following link to choose a new one:\n\n\
+                    {}/password/reset?token={}\n\n\
+                    If this was not you, you can ignore this email.\n",
+                    self.public_url, token
+                ),
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod mailer_tests {
+    use std::sync::Arc;
+
+    use super::{FileMailer, Notifier};
+
+    #[tokio::test]
+    async fn verification_link_is_written_to_file() {
+        let dir = std::env::temp_dir()
+            .join(format!("mailer-test-{}", uuid::Uuid::new_v4()));
+        let notifier = Notifier::new(
+            Arc::new(FileMailer::new(&dir)),
+            "http://localhost:8080/",
+        );
+
+        notifier
+            .send_verification("test@email.com", "abc")
+            .await
+            .unwrap();
+
+        let mut files = std::fs::read_dir(&dir).unwrap();
+        let mail =
+            std::fs::read_to_string(files.next().unwrap().unwrap().path())
+                .unwrap();
+        assert!(mail.starts_with("To: test@email.com\n"));
+        assert!(
+            mail.contains("http://localhost:8080/verify-email?token=abc")
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -3,12 +3,26 @@ use std::env;
 use handle_errors::return_error;
 use rustwebdev::{
     config::Config,
+    mailer::{self, Notifier},
+    rate_limit::{self, Policy, RateLimiter},
     routes::{self},
     store::Store,
+    types::account::Role,
 };
 use tracing_subscriber::fmt::format::FmtSpan;
 use warp::{http::Method, Filter};
 
+/// First path segments of the authentication endpoints, which get a
+/// rate limit of their own
+const AUTH_PATHS: &[&str] = &[
+    "registration",
+    "verify-email",
+    "password",
+    "login",
+    "token",
+    "logout",
+];
+
 #[tokio::main]
 async fn main() -> Result<(), handle_errors::Error> {
     dotenvy::dotenv().ok();
@@ -30,15 +44,39 @@ async fn main() -> Result<(), handle_errors::Error> {
         config.db_name,
     ))
     .await
-    .map_err(|e| handle_errors::Error::DatabaseQueryError(e))?;
+    .map_err(handle_errors::Error::DatabaseQueryError)?;
 
     sqlx::migrate!()
         .run(&store.clone().connection)
         .await
-        .map_err(|e| handle_errors::Error::MigrationError(e))?;
+        .map_err(handle_errors::Error::MigrationError)?;
 
+    let legacy_auth_header = config.legacy_auth_header;
+    let auth = routes::auth::auth(store.clone(), legacy_auth_header);
+    let optional_auth =
+        routes::auth::optional_auth(store.clone(), legacy_auth_header);
+    let moderator_auth = routes::auth::require_role(
+        store.clone(),
+        legacy_auth_header,
+        Role::Moderator,
+    );
+    let admin_auth = routes::auth::require_role(
+        store.clone(),
+        legacy_auth_header,
+        Role::Admin,
+    );
+    let rate_limit_backend =
+        rate_limit::from_config(&config, store.clone())?;
+    routes::auth::prune_login_attempts(
+        store.clone(),
+        config.login_limits(),
+    );
     let store_filter = warp::any().map(move || store.clone());
 
+    let notifier =
+        Notifier::new(mailer::from_config(&config)?, &config.public_url);
+    let notifier_filter = warp::any().map(move || notifier.clone());
+
     tracing_subscriber::fmt()
         .with_env_filter(log_filter)
         .with_span_events(FmtSpan::CLOSE)
@@ -46,9 +84,24 @@ async fn main() -> Result<(), handle_errors::Error> {
 
     let cors = warp::cors()
         .allow_any_origin()
-        .allow_header("content-type")
+        .allow_headers(if legacy_auth_header {
+            vec!["content-type", "if-match", "authorization", "auth"]
+        } else {
+            vec!["content-type", "if-match", "authorization"]
+        })
+        .expose_headers(vec![
+            "link",
+            "x-total-count",
+            "etag",
+            "retry-after",
+            "ratelimit-limit",
+            "ratelimit-remaining",
+            "ratelimit-reset",
+            "ratelimit-policy",
+        ])
         .allow_methods(&[
             Method::PUT,
+            Method::PATCH,
             Method::DELETE,
             Method::GET,
             Method::POST,
@@ -58,30 +111,66 @@ async fn main() -> Result<(), handle_errors::Error> {
         .and(warp::path("questions"))
         .and(warp::path::end())
         .and(warp::query())
+        .and(optional_auth.clone())
         .and(store_filter.clone())
         .and_then(routes::question::get_questions);
 
+    let search_questions = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path("search"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::question::search_questions);
+
+    let get_question = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::question::get_question);
+
     let update_question = warp::put()
         .and(warp::path("questions"))
         .and(warp::path::param::<i32>())
         .and(warp::path::end())
-        .and(routes::auth::auth())
+        .and(auth.clone())
+        .and(warp::header::optional::<String>("if-match"))
         .and(store_filter.clone())
         .and(warp::body::json())
         .and_then(routes::question::update_question);
 
+    let patch_question = warp::patch()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::question::patch_question);
+
     let delete_question = warp::delete()
         .and(warp::path("questions"))
         .and(warp::path::param::<i32>())
         .and(warp::path::end())
-        .and(routes::auth::auth())
+        .and(auth.clone())
         .and(store_filter.clone())
         .and_then(routes::question::delete_question);
 
+    let restore_question = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("restore"))
+        .and(warp::path::end())
+        .and(moderator_auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::question::restore_question);
+
     let add_question = warp::post()
         .and(warp::path("questions"))
         .and(warp::path::end())
-        .and(routes::auth::auth())
+        .and(auth.clone())
         .and(store_filter.clone())
         .and(warp::body::json())
         .and_then(routes::question::add_question);
@@ -89,35 +178,485 @@ async fn main() -> Result<(), handle_errors::Error> {
     let add_answers = warp::post()
         .and(warp::path("answers"))
         .and(warp::path::end())
-        .and(routes::auth::auth())
+        .and(auth.clone())
         .and(store_filter.clone())
         .and(warp::body::json())
         .and_then(routes::answer::add_answers);
 
+    let get_answers = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("answers"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::answer::get_answers);
+
+    let get_answer = warp::get()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::answer::get_answer);
+
+    let update_answer = warp::put()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::answer::update_answer);
+
+    let delete_answer = warp::delete()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::answer::delete_answer);
+
+    let vote_question = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::vote::vote_question);
+
+    let unvote_question = warp::delete()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::vote::unvote_question);
+
+    let vote_answer = warp::post()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::vote::vote_answer);
+
+    let unvote_answer = warp::delete()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::vote::unvote_answer);
+
+    let accept_answer = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("accept"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::question::accept_answer);
+
+    let unaccept_answer = warp::delete()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("accept"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::question::unaccept_answer);
+
+    let get_question_comments = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("comments"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::comment::get_question_comments);
+
+    let add_question_comment = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("comments"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::comment::add_question_comment);
+
+    let get_answer_comments = warp::get()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("comments"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::comment::get_answer_comments);
+
+    let add_answer_comment = warp::post()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("comments"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::comment::add_answer_comment);
+
+    let update_comment = warp::put()
+        .and(warp::path("comments"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::comment::update_comment);
+
+    let delete_comment = warp::delete()
+        .and(warp::path("comments"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::comment::delete_comment);
+
+    let get_question_revisions = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("revisions"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::question::get_question_revisions);
+
+    let restore_question_revision = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("revisions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("restore"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::question::restore_question_revision);
+
+    let get_answer_revisions = warp::get()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("revisions"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::answer::get_answer_revisions);
+
+    let get_tags = warp::get()
+        .and(warp::path("tags"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::tag::get_tags);
+
+    let update_tag = warp::put()
+        .and(warp::path("tags"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(moderator_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::tag::update_tag);
+
+    let add_tag_synonym = warp::post()
+        .and(warp::path("tags"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("synonyms"))
+        .and(warp::path::end())
+        .and(moderator_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::tag::add_tag_synonym);
+
+    let get_accounts = warp::get()
+        .and(warp::path("accounts"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::account::get_accounts);
+
+    let update_role = warp::put()
+        .and(warp::path("accounts"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("role"))
+        .and(warp::path::end())
+        .and(admin_auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::account::update_role);
+
+    let get_me = warp::get()
+        .and(warp::path("me"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::account::get_me);
+
+    let change_password = warp::put()
+        .and(warp::path("me"))
+        .and(warp::path("password"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::account::change_password);
+
+    let change_email = warp::put()
+        .and(warp::path("me"))
+        .and(warp::path("email"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(notifier_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::account::change_email);
+
+    let change_display_name = warp::put()
+        .and(warp::path("me"))
+        .and(warp::path("display-name"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::account::change_display_name);
+
+    let get_user = warp::get()
+        .and(warp::path("users"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::user::get_user);
+
+    let get_user_questions = warp::get()
+        .and(warp::path("users"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("questions"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::user::get_user_questions);
+
+    let get_user_answers = warp::get()
+        .and(warp::path("users"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("answers"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::user::get_user_answers);
+
+    let delete_me = warp::delete()
+        .and(warp::path("me"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::account::delete_me);
+
     let registration = warp::post()
         .and(warp::path("registration"))
         .and(warp::path::end())
         .and(store_filter.clone())
+        .and(notifier_filter.clone())
         .and(warp::body::json())
         .and_then(routes::auth::register);
 
+    let verify_email = warp::get()
+        .and(warp::path("verify-email"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::auth::verify_email);
+
+    let resend_verification = warp::post()
+        .and(warp::path("verify-email"))
+        .and(warp::path("resend"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and(notifier_filter.clone())
+        .and_then(routes::auth::resend_verification);
+
+    let forgot_password = warp::post()
+        .and(warp::path("password"))
+        .and(warp::path("forgot"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(notifier_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::auth::forgot_password);
+
+    let reset_password_form = warp::get()
+        .and(warp::path("password"))
+        .and(warp::path("reset"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and_then(routes::auth::reset_password_form);
+
+    let reset_password = warp::post()
+        .and(warp::path("password"))
+        .and(warp::path("reset"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(warp::body::json().or(warp::body::form()).unify())
+        .and_then(routes::auth::reset_password);
+
+    let login_limits = config.login_limits();
     let login = warp::post()
         .and(warp::path("login"))
         .and(warp::path::end())
         .and(store_filter.clone())
+        .and(warp::any().map(move || login_limits))
+        .and(routes::auth::client_ip(config.trust_forwarded_for))
         .and(warp::body::json())
         .and_then(routes::auth::login);
 
-    let routes = get_questions
+    let refresh_token = warp::post()
+        .and(warp::path("token"))
+        .and(warp::path("refresh"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::auth::refresh);
+
+    let logout = warp::post()
+        .and(warp::path("logout"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::auth::logout);
+
+    let logout_all = warp::post()
+        .and(warp::path("logout"))
+        .and(warp::path("all"))
+        .and(warp::path::end())
+        .and(auth.clone())
+        .and(store_filter.clone())
+        .and_then(routes::auth::logout_all);
+
+    let question_routes = get_questions
+        .or(search_questions)
+        .or(get_question)
         .or(add_question)
         .or(update_question)
+        .or(patch_question)
         .or(delete_question)
-        .or(add_answers)
-        .or(registration)
+        .or(restore_question)
+        .or(get_question_revisions)
+        .or(restore_question_revision)
+        .or(accept_answer)
+        .or(unaccept_answer)
+        .boxed();
+
+    let answer_routes = add_answers
+        .or(get_answers)
+        .or(get_answer)
+        .or(update_answer)
+        .or(delete_answer)
+        .or(get_answer_revisions)
+        .boxed();
+
+    let vote_and_comment_routes = vote_question
+        .or(unvote_question)
+        .or(vote_answer)
+        .or(unvote_answer)
+        .or(get_question_comments)
+        .or(add_question_comment)
+        .or(get_answer_comments)
+        .or(add_answer_comment)
+        .or(update_comment)
+        .or(delete_comment)
+        .boxed();
+
+    let account_routes = get_accounts
+        .or(update_role)
+        .or(get_me)
+        .or(change_password)
+        .or(change_email)
+        .or(change_display_name)
+        .or(delete_me)
+        .or(get_user)
+        .or(get_user_questions)
+        .or(get_user_answers)
+        .boxed();
+
+    let api_routes = question_routes
+        .or(answer_routes)
+        .or(vote_and_comment_routes)
+        .or(account_routes)
+        .or(get_tags)
+        .or(update_tag)
+        .or(add_tag_synonym)
+        .boxed();
+
+    let auth_routes = registration
+        .or(verify_email)
+        .or(resend_verification)
+        .or(forgot_password)
+        .or(reset_password_form)
+        .or(reset_password)
         .or(login)
-        .with(cors)
-        .with(warp::trace::request())
-        .recover(return_error);
+        .or(refresh_token)
+        .or(logout)
+        .or(logout_all)
+        .boxed();
+
+    let trust_forwarded_for = config.trust_forwarded_for;
+    let limiter = |name, requests_per_minute| {
+        RateLimiter::new(
+            name,
+            Policy::per_minute(requests_per_minute),
+            rate_limit_backend.clone(),
+        )
+    };
+
+    let auth_requests =
+        rate_limit::path_in(AUTH_PATHS).and(rate_limit::limit(
+            limiter("auth", config.rate_limit_auth),
+            rate_limit::by_ip(trust_forwarded_for),
+            auth_routes,
+        ));
+
+    let read_requests = rate_limit::path_not_in(AUTH_PATHS)
+        .and(rate_limit::safe_method())
+        .and(rate_limit::limit(
+            limiter("reads", config.rate_limit_reads),
+            rate_limit::by_ip(trust_forwarded_for),
+            api_routes.clone(),
+        ));
+
+    let write_requests = rate_limit::path_not_in(AUTH_PATHS)
+        .and(rate_limit::unsafe_method())
+        .and(rate_limit::limit(
+            limiter("writes", config.rate_limit_writes),
+            rate_limit::by_account(
+                legacy_auth_header,
+                trust_forwarded_for,
+            ),
+            api_routes,
+        ));
+
+    let routes = rate_limit::limit(
+        limiter("global", config.rate_limit_global),
+        rate_limit::by_ip(trust_forwarded_for),
+        auth_requests
+            .or(read_requests)
+            .unify()
+            .or(write_requests)
+            .unify(),
+    )
+    .with(cors)
+    .with(warp::trace::request())
+    .recover(return_error);
 
     tracing::info!(
         "Q&A service build ID {}",
@@ -0,0 +1,606 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, future,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use handle_errors::Error;
+use warp::{
+    http::{HeaderMap, Method},
+    path::Peek,
+    Filter, Reply,
+};
+
+use crate::{
+    config::Config,
+    routes::auth::{client_ip, decrypt_token, token},
+    store::Store,
+};
+
+/// Buckets kept by `MemoryBackend` before the least recently used ones
+/// are dropped
+const MAX_MEMORY_BUCKETS: usize = 100_000;
+/// How often `PostgresBackend` deletes the buckets which are full again
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Token bucket allowing bursts of `capacity` requests, refilled evenly
+/// over `window` seconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Policy {
+    pub capacity: u32,
+    pub window: u64,
+}
+
+impl Policy {
+    pub fn per_minute(requests: u32) -> Self {
+        Policy {
+            capacity: requests,
+            window: 60,
+        }
+    }
+
+    fn refill_per_second(&self) -> f64 {
+        self.capacity as f64 / self.window as f64
+    }
+}
+
+/// Outcome of taking a token from a bucket
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Taken {
+    pub allowed: bool,
+    /// Tokens left in the bucket afterwards
+    pub tokens: f64,
+}
+
+/// Keeps the token buckets, in memory or shared between instances in
+/// Postgres
+#[async_trait]
+pub trait Backend: fmt::Debug + Send + Sync {
+    async fn take(
+        &self,
+        key: &str,
+        policy: &Policy,
+    ) -> Result<Taken, Error>;
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    policy: Policy,
+}
+
+impl Bucket {
+    fn new(policy: Policy, now: Instant) -> Self {
+        Bucket {
+            tokens: policy.capacity as f64,
+            updated: now,
+            policy,
+        }
+    }
+
+    fn refilled(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated);
+        (self.tokens
+            + self.policy.refill_per_second() * elapsed.as_secs_f64())
+        .min(self.policy.capacity as f64)
+    }
+
+    fn take(&mut self, now: Instant) -> Taken {
+        self.tokens = self.refilled(now);
+        self.updated = now;
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+        Taken {
+            allowed,
+            tokens: self.tokens,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    /// Bucket of every key with the number of its last use
+    by_key: HashMap<String, (Bucket, u64)>,
+    /// Keys by the number of their last use, least recently used first
+    by_use: BTreeMap<u64, String>,
+    uses: u64,
+}
+
+/// Buckets of a single instance, every instance limits on its own
+#[derive(Debug)]
+pub struct MemoryBackend {
+    max_buckets: usize,
+    buckets: Mutex<Buckets>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::with_max_buckets(MAX_MEMORY_BUCKETS)
+    }
+
+    /// Keep at most `max_buckets`, the least recently used bucket is
+    /// dropped to make room for a new one
+    pub fn with_max_buckets(max_buckets: usize) -> Self {
+        MemoryBackend {
+            max_buckets: max_buckets.max(1),
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Backend for MemoryBackend {
+    async fn take(
+        &self,
+        key: &str,
+        policy: &Policy,
+    ) -> Result<Taken, Error> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets {
+            by_key,
+            by_use,
+            uses,
+        } = &mut *buckets;
+        *uses += 1;
+
+        if let Some((bucket, last_use)) = by_key.get_mut(key) {
+            by_use.remove(last_use);
+            by_use.insert(*uses, key.to_string());
+            *last_use = *uses;
+            bucket.policy = *policy;
+            return Ok(bucket.take(now));
+        }
+
+        while by_key.len() >= self.max_buckets {
+            match by_use.pop_first() {
+                Some((_, oldest)) => by_key.remove(&oldest),
+                None => break,
+            };
+        }
+        let mut bucket = Bucket::new(*policy, now);
+        let taken = bucket.take(now);
+        by_key.insert(key.to_string(), (bucket, *uses));
+        by_use.insert(*uses, key.to_string());
+        Ok(taken)
+    }
+}
+
+/// Buckets in the `rate_limits` table, shared by all instances using the
+/// same database
+#[derive(Debug)]
+pub struct PostgresBackend {
+    store: Store,
+    last_prune: Mutex<Instant>,
+}
+
+impl PostgresBackend {
+    pub fn new(store: Store) -> Self {
+        PostgresBackend {
+            store,
+            last_prune: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Delete the full buckets in the background, at most once per
+    /// `PRUNE_INTERVAL`. A failure only delays it to the next interval
+    fn prune(&self) {
+        let mut last_prune = self.last_prune.lock().unwrap();
+        if last_prune.elapsed() < PRUNE_INTERVAL {
+            return;
+        }
+        *last_prune = Instant::now();
+
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            if let Err(e) = store.prune_rate_limits().await {
+                tracing::event!(
+                    tracing::Level::WARN,
+                    "rate limits not pruned: {}",
+                    e
+                );
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl Backend for PostgresBackend {
+    async fn take(
+        &self,
+        key: &str,
+        policy: &Policy,
+    ) -> Result<Taken, Error> {
+        let (allowed, tokens) = self
+            .store
+            .take_rate_limit_token(
+                key,
+                policy.capacity as f64,
+                policy.refill_per_second(),
+            )
+            .await?;
+        self.prune();
+        Ok(Taken { allowed, tokens })
+    }
+}
+
+/// Create the backend selected by `config.rate_limit_backend`
+pub fn from_config(
+    config: &Config,
+    store: Store,
+) -> Result<Arc<dyn Backend>, Error> {
+    match config.rate_limit_backend.as_str() {
+        "memory" => Ok(Arc::new(MemoryBackend::new())),
+        "postgres" => Ok(Arc::new(PostgresBackend::new(store))),
+        backend => Err(Error::InvalidParameter(format!(
+            "rate_limit_backend={}",
+            backend
+        ))),
+    }
+}
+
+/// State of a bucket after a request, sent as `RateLimit-*` headers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset: u64,
+    pub window: u64,
+}
+
+impl RateLimitStatus {
+    fn new(policy: &Policy, tokens: f64) -> Self {
+        RateLimitStatus {
+            limit: policy.capacity,
+            remaining: tokens.max(0.0).floor() as u32,
+            reset: ((policy.capacity as f64 - tokens).max(0.0)
+                / policy.refill_per_second())
+            .ceil() as u64,
+            window: policy.window,
+        }
+    }
+
+    /// Add the `RateLimit-*` headers, unless `headers` already describe a
+    /// limit with fewer requests remaining
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        let remaining = headers
+            .get("ratelimit-remaining")
+            .and_then(|remaining| remaining.to_str().ok())
+            .and_then(|remaining| remaining.parse::<u32>().ok());
+        if matches!(remaining, Some(remaining) if remaining <= self.remaining)
+        {
+            return;
+        }
+
+        headers.insert("ratelimit-limit", self.limit.into());
+        headers.insert("ratelimit-remaining", self.remaining.into());
+        headers.insert("ratelimit-reset", self.reset.into());
+        headers.insert(
+            "ratelimit-policy",
+            format!("{};w={}", self.limit, self.window)
+                .parse()
+                .expect("valid header value"),
+        );
+    }
+}
+
+/// A named rate limit, the name separates its buckets from those of
+/// other limiters in the same backend
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    name: &'static str,
+    policy: Policy,
+    backend: Arc<dyn Backend>,
+}
+
+impl RateLimiter {
+    /// A `policy` with a capacity of 0 disables the limiter
+    pub fn new(
+        name: &'static str,
+        policy: Policy,
+        backend: Arc<dyn Backend>,
+    ) -> Self {
+        RateLimiter {
+            name,
+            policy,
+            backend,
+        }
+    }
+
+    /// Take a token from the bucket of `key`, rejects with `RateLimited`
+    /// once it is empty. When the backend fails the request is let
+    /// through, the limiter should not take the service down with it
+    pub async fn check(
+        &self,
+        key: &str,
+    ) -> Result<Option<RateLimitStatus>, Error> {
+        if self.policy.capacity == 0 {
+            return Ok(None);
+        }
+
+        match self
+            .backend
+            .take(&format!("{}:{}", self.name, key), &self.policy)
+            .await
+        {
+            Ok(taken) => {
+                let status =
+                    RateLimitStatus::new(&self.policy, taken.tokens);
+                if taken.allowed {
+                    Ok(Some(status))
+                } else {
+                    Err(Error::RateLimited {
+                        limit: status.limit,
+                        reset: status.reset,
+                        retry_after: ((1.0 - taken.tokens)
+                            / self.policy.refill_per_second())
+                        .ceil()
+                        .max(1.0)
+                            as u64,
+                    })
+                }
+            }
+            Err(e) => {
+                tracing::event!(
+                    tracing::Level::WARN,
+                    "rate limit {} not checked: {}",
+                    self.name,
+                    e
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Apply `limiter` to `route`, one bucket per key extracted by `key`.
+/// The token is taken before `route` is matched, so put filters selecting
+/// the requests of the group, like `path_in()` or `safe_method()`, in
+/// front of it
+pub fn limit<K, R, T>(
+    limiter: RateLimiter,
+    key: K,
+    route: R,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection>
+       + Clone
+where
+    K: Filter<Extract = (String,), Error = warp::Rejection>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    R: Filter<Extract = (T,), Error = warp::Rejection>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    T: Reply,
+{
+    key.and_then(move |key: String| {
+        let limiter = limiter.clone();
+        async move { limiter.check(&key).await.map_err(warp::reject::custom) }
+    })
+    .and(route)
+    .map(|status: Option<RateLimitStatus>, reply: T| {
+        let mut response = reply.into_response();
+        if let Some(status) = status {
+            status.apply(response.headers_mut());
+        }
+        response
+    })
+}
+
+/// Key requests by client IP
+pub fn by_ip(
+    trust_forwarded_for: bool,
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    client_ip(trust_forwarded_for).map(ip_key)
+}
+
+/// Key requests by the account of the token sent, anonymous requests by
+/// client IP. The token is only decrypted here, `auth()` still has to
+/// verify it
+pub fn by_account(
+    legacy_header: bool,
+    trust_forwarded_for: bool,
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    token(legacy_header)
+        .and(client_ip(trust_forwarded_for))
+        .map(|token: Option<String>, ip: Option<IpAddr>| {
+            match token.and_then(|token| decrypt_token(&token).ok()) {
+                Some(session) => {
+                    format!("account:{}", session.account_id.0)
+                }
+                None => ip_key(ip),
+            }
+        })
+}
+
+fn ip_key(ip: Option<IpAddr>) -> String {
+    match ip {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// Only let requests through whose first path segment is one of
+/// `segments`, without consuming the path
+pub fn path_in(
+    segments: &'static [&'static str],
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    first_segment(segments, true)
+}
+
+/// Only let requests through whose first path segment is none of
+/// `segments`, without consuming the path
+pub fn path_not_in(
+    segments: &'static [&'static str],
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    first_segment(segments, false)
+}
+
+fn first_segment(
+    segments: &'static [&'static str],
+    listed: bool,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::path::peek()
+        .and_then(move |peek: Peek| {
+            let first = peek.segments().next().unwrap_or_default();
+            future::ready(if segments.contains(&first) == listed {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            })
+        })
+        .untuple_one()
+}
+
+/// Only let reading requests (`GET`, `HEAD`) through
+pub fn safe_method(
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    method_is_safe(true)
+}
+
+/// Only let writing requests (everything but `GET`, `HEAD`) through
+pub fn unsafe_method(
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    method_is_safe(false)
+}
+
+fn method_is_safe(
+    safe: bool,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::method()
+        .and_then(move |method: Method| {
+            let is_safe = method == Method::GET || method == Method::HEAD;
+            future::ready(if is_safe == safe {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            })
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use handle_errors::return_error;
+    use warp::{http::StatusCode, Filter};
+
+    use super::{
+        limit, path_in, Backend, Bucket, MemoryBackend, Policy,
+        RateLimiter,
+    };
+
+    #[test]
+    fn bucket_empties_and_refills() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(Policy::per_minute(2), start);
+
+        assert!(bucket.take(start).allowed);
+        assert!(bucket.take(start).allowed);
+        assert!(!bucket.take(start).allowed);
+        assert!(!bucket.take(start + Duration::from_secs(29)).allowed);
+        assert!(bucket.take(start + Duration::from_secs(30)).allowed);
+
+        let taken = bucket.take(start + Duration::from_secs(600));
+        assert!(taken.allowed);
+        assert_eq!(taken.tokens, 1.0);
+    }
+
+    #[tokio::test]
+    async fn memory_backend_drops_least_recently_used_bucket() {
+        let backend = MemoryBackend::with_max_buckets(2);
+        let policy = Policy::per_minute(1);
+
+        assert!(backend.take("a", &policy).await.unwrap().allowed);
+        assert!(backend.take("b", &policy).await.unwrap().allowed);
+        assert!(!backend.take("a", &policy).await.unwrap().allowed);
+        // Makes room by dropping "b", "a" was used more recently
+        assert!(backend.take("c", &policy).await.unwrap().allowed);
+        assert!(!backend.take("a", &policy).await.unwrap().allowed);
+        assert!(backend.take("b", &policy).await.unwrap().allowed);
+        assert_eq!(backend.buckets.lock().unwrap().by_key.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn limit_sends_headers_and_rejects_when_empty() {
+        let limiter = RateLimiter::new(
+            "test",
+            Policy::per_minute(1),
+            Arc::new(MemoryBackend::new()),
+        );
+        let filter = limit(
+            limiter,
+            warp::path::end().map(|| "ip:127.0.0.1".to_string()),
+            warp::path::end().map(|| "ok"),
+        )
+        .recover(return_error);
+
+        let res = warp::test::request().reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["ratelimit-limit"], "1");
+        assert_eq!(res.headers()["ratelimit-remaining"], "0");
+        assert_eq!(res.headers()["ratelimit-reset"], "60");
+        assert_eq!(res.headers()["ratelimit-policy"], "1;w=60");
+
+        let res = warp::test::request().reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["retry-after"], "60");
+        assert_eq!(res.headers()["ratelimit-remaining"], "0");
+    }
+
+    #[tokio::test]
+    async fn disabled_limiter_sends_no_headers() {
+        let limiter = RateLimiter::new(
+            "test",
+            Policy::per_minute(0),
+            Arc::new(MemoryBackend::new()),
+        );
+        let filter = limit(
+            limiter,
+            warp::path::end().map(|| "ip:127.0.0.1".to_string()),
+            warp::path::end().map(|| "ok"),
+        );
+
+        let res = warp::test::request().reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key("ratelimit-limit"));
+    }
+
+    #[tokio::test]
+    async fn path_in_does_not_consume_the_path() {
+        let filter = path_in(&["login"])
+            .and(warp::path("login"))
+            .and(warp::path::end())
+            .map(|| "ok");
+
+        assert!(
+            warp::test::request().path("/login").matches(&filter).await
+        );
+        assert!(
+            !warp::test::request()
+                .path("/questions")
+                .matches(&filter)
+                .await
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use reqwest::StatusCode;
+
+use crate::{
+    mailer::Notifier,
+    routes::auth::{
+        hash_password, send_verification, start_session, verify_password,
+    },
+    store::Store,
+    types::{
+        account::{
+            check_email, check_password, validate, Account, AccountId,
+            AccountProfile, EmailChange, PasswordChange, Session,
+            UpdatedRole,
+        },
+        user::{normalize_display_name, DisplayName},
+    },
+};
+
+/// Return every account with its role, only allowed for admins
+pub async fn get_accounts(
+    _session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_accounts().await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Change the role of an account, only allowed for admins. The new role
+/// takes effect with the next token issued to the account
+pub async fn update_role(
+    id: i32,
+    _session: Session,
+    store: Store,
+    updated_role: UpdatedRole,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.update_role(&AccountId(id), updated_role.role).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Return the logged in account
+pub async fn get_me(
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account = store.get_account_by_id(&session.account_id).await?;
+
+    Ok(warp::reply::json(&AccountProfile::from(account)))
+}
+
+/// Change the password of the logged in account. Every session is logged
+/// out, the response carries the tokens of a new one
+pub async fn change_password(
+    session: Session,
+    store: Store,
+    change: PasswordChange,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    validate(vec![(
+        "new_password",
+        check_password(&change.new_password),
+    )])?;
+    let account = store.get_account_by_id(&session.account_id).await?;
+    confirm_password(&account, &change.current_password)?;
+
+    store
+        .change_password(
+            &session.account_id,
+            &hash_password(change.new_password.as_bytes()),
+        )
+        .await?;
+    let tokens =
+        start_session(&store, session.account_id, account.role).await?;
+
+    Ok(warp::reply::json(&tokens))
+}
+
+/// Change the email address of the logged in account and send a
+/// verification link to the new one
+pub async fn change_email(
+    session: Session,
+    store: Store,
+    notifier: Notifier,
+    change: EmailChange,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    validate(vec![("email", check_email(&change.email))])?;
+    let account = store.get_account_by_id(&session.account_id).await?;
+    confirm_password(&account, &change.password)?;
+
+    store
+        .change_email(&session.account_id, &change.email)
+        .await?;
+    // The address is changed at this point, a failed email can be sent
+    // again via `/verify-email/resend`
+    if let Err(e) = send_verification(&notifier, &change.email).await {
+        tracing::event!(tracing::Level::ERROR, "{}", e);
+    }
+
+    Ok(warp::reply::with_status("Email changed", StatusCode::OK))
+}
+
+/// Change the display name shown on the public profile of the logged in
+/// account
+pub async fn change_display_name(
+    session: Session,
+    store: Store,
+    change: DisplayName,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let display_name = normalize_display_name(&change.display_name)?;
+
+    match store
+        .set_display_name(&session.account_id, &display_name)
+        .await
+    {
+        Ok(_) => Ok(warp::reply::with_status(
+            "Display name changed",
+            StatusCode::OK,
+        )),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Delete the logged in account. Its questions and answers stay, but
+/// belong to an anonymized account from now on
+pub async fn delete_me(
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.delete_account(&session.account_id).await {
+        Ok(_) => {
+            Ok(warp::reply::with_status("Account deleted", StatusCode::OK))
+        }
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Reject with `WrongPassword` unless `password` is the one of `account`
+fn confirm_password(
+    account: &Account,
+    password: &str,
+) -> Result<(), warp::Rejection> {
+    match verify_password(&account.password, password.as_bytes()) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            Err(warp::reject::custom(handle_errors::Error::WrongPassword))
+        }
+        Err(e) => Err(warp::reject::custom(
+            handle_errors::Error::ArgonLibraryError(e),
+        )),
+    }
+}
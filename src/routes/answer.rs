@@ -1,15 +1,69 @@
 use warp::hyper::StatusCode;
 
 use crate::{
-    profanity::check_profanity, store::Store, types::{answer::NewAnswer, account::Session},
+    profanity::check_profanity,
+    store::Store,
+    types::{
+        account::{Role, Session},
+        answer::{NewAnswer, UpdatedAnswer},
+    },
 };
 
-///
+/// Return every answer given to a question
+pub async fn get_answers(
+    question_id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !store.question_exists(question_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+
+    match store.get_answers(question_id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Return a single answer by its id
+pub async fn get_answer(
+    id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_answer(id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Return the edit history of an answer, oldest revision first
+pub async fn get_answer_revisions(
+    id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.get_answer(id).await?;
+
+    match store.get_answer_revisions(id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Add an answer to an existing question, the logged in account needs a
+/// verified email address
 pub async fn add_answers(
-    _session: Session,
+    session: Session,
     store: Store,
     new_answer: NewAnswer,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+    if !store.get_account_by_id(&account_id).await?.email_verified {
+        return Err(warp::reject::custom(
+            handle_errors::Error::EmailNotVerified,
+        ));
+    }
+
     let content =
         match check_profanity(new_answer.content.to_string()).await {
             Ok(res) => res,
@@ -19,10 +73,73 @@ pub async fn add_answers(
         content,
         question_id: new_answer.question_id,
     };
-    match store.add_answer(answer).await {
+    match store.add_answer(answer, account_id).await {
         Ok(_) => {
             Ok(warp::reply::with_status("Answer added", StatusCode::OK))
         }
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
+
+/// Update an answer owned by the logged in account, moderators can update
+/// any answer
+pub async fn update_answer(
+    id: i32,
+    session: Session,
+    store: Store,
+    answer: UpdatedAnswer,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    store.get_answer(id).await?;
+
+    if session.role < Role::Moderator
+        && !store.is_answer_owner(id, &account_id).await?
+    {
+        return Err(warp::reject::custom(
+            handle_errors::Error::Forbidden,
+        ));
+    }
+
+    let content = match check_profanity(answer.content).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+    let answer = UpdatedAnswer { content };
+
+    match store.update_answer(answer, id, account_id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Delete an answer owned by the logged in account, moderators can delete
+/// any answer
+pub async fn delete_answer(
+    id: i32,
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    store.get_answer(id).await?;
+
+    if session.role < Role::Moderator
+        && !store.is_answer_owner(id, &account_id).await?
+    {
+        return Err(warp::reject::custom(
+            handle_errors::Error::Forbidden,
+        ));
+    }
+
+    match store.delete_answer(id).await {
+        Ok(true) => Ok(warp::reply::with_status(
+            format!("Answer {} deleted", id),
+            StatusCode::OK,
+        )),
+        Ok(false) => Err(warp::reject::custom(
+            handle_errors::Error::AnswerNotFound,
+        )),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
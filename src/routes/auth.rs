@@ -1,45 +1,160 @@
-use std::{env, future};
+use std::{
+    collections::HashMap,
+    env, future,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::prelude::*;
 use rand::Rng;
 use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
 use warp::Filter;
 
 use crate::{
+    config::LoginLimits,
+    mailer::Notifier,
     store::Store,
-    types::account::{Account, AccountId, Session},
+    types::account::{
+        check_password, validate, AccountId, AccountProfile,
+        ForgotPassword, LoginRequest, PasswordReset, RefreshRequest,
+        RegisterRequest, Role, Session, TokenPair,
+    },
 };
 
+/// Purpose claim of the tokens in email verification links
+const VERIFY_EMAIL_PURPOSE: &str = "verify_email";
+/// How long an email verification link stays valid
+const VERIFY_EMAIL_LIFETIME_HOURS: i64 = 48;
+/// How often the expired failed login counters are deleted
+const LOGIN_ATTEMPTS_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Create an unverified account and send the verification link to its
+/// email address. Invalid email addresses and weak passwords are rejected
+/// with `ValidationFailed`
 pub async fn register(
     store: Store,
-    account: Account,
+    notifier: Notifier,
+    request: RegisterRequest,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let hashed_password = hash_password(account.password.as_bytes());
+    request.validate()?;
+    let hashed_password = hash_password(request.password.as_bytes());
 
-    let account = Account {
-        id: account.id,
-        email: account.email,
-        password: hashed_password,
-    };
+    match store.add_account(&request.email, &hashed_password).await {
+        Ok(account) => {
+            // The account exists at this point, a failed email can be
+            // sent again via `/verify-email/resend`
+            if let Err(e) =
+                send_verification(&notifier, &account.email).await
+            {
+                tracing::event!(tracing::Level::ERROR, "{}", e);
+            }
+            Ok(warp::reply::with_status(
+                warp::reply::json(&AccountProfile::from(account)),
+                StatusCode::CREATED,
+            ))
+        }
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
 
-    match store.add_account(account).await {
-        Ok(_) => {
-            Ok(warp::reply::with_status("Account Added", StatusCode::OK))
+/// Verify the email address of an account with the token from the link
+/// sent on registration
+pub async fn verify_email(
+    params: HashMap<String, String>,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let token = params
+        .get("token")
+        .ok_or(handle_errors::Error::MissingParameters)?;
+    let email = verify_email_token(token, VERIFY_EMAIL_PURPOSE)?;
+
+    match store.verify_email(&email).await {
+        Ok(true) => {
+            Ok(warp::reply::with_status("Email verified", StatusCode::OK))
         }
+        Ok(false) => Err(warp::reject::custom(
+            handle_errors::Error::AccountNotFound,
+        )),
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
 
+/// Send the verification link of the logged in account again
+pub async fn resend_verification(
+    session: Session,
+    store: Store,
+    notifier: Notifier,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account = store.get_account_by_id(&session.account_id).await?;
+    if account.email_verified {
+        return Ok(warp::reply::with_status(
+            "Email already verified",
+            StatusCode::OK,
+        ));
+    }
+
+    match send_verification(&notifier, &account.email).await {
+        Ok(_) => Ok(warp::reply::with_status(
+            "Verification email sent",
+            StatusCode::OK,
+        )),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+pub(crate) async fn send_verification(
+    notifier: &Notifier,
+    email: &str,
+) -> Result<(), handle_errors::Error> {
+    let token = issue_email_token(
+        email,
+        VERIFY_EMAIL_PURPOSE,
+        chrono::Duration::hours(VERIFY_EMAIL_LIFETIME_HOURS),
+    );
+    notifier.send_verification(email, &token).await
+}
+
 pub fn hash_password(password: &[u8]) -> String {
     let salt = rand::thread_rng().gen::<[u8; 32]>();
     let config = argon2::Config::default();
     argon2::hash_encoded(password, &salt, &config).unwrap()
 }
 
+/// How long a password reset link stays valid
+const PASSWORD_RESET_LIFETIME_MINUTES: i64 = 60;
+/// Lifetime of the access tokens used in the `auth` header
+const ACCESS_TOKEN_LIFETIME_MINUTES: i64 = 15;
+/// Lifetime of the refresh tokens, each refresh issues a new one
+const REFRESH_TOKEN_LIFETIME_DAYS: i64 = 30;
+
+/// Log in with email and password. Failed attempts are counted per email
+/// and per client IP, too many of them lock further attempts out for a
+/// while, answered with `TooManyRequests`. A successful login only resets
+/// the email counter, the IP counter has to expire on its own so logging
+/// into an own account does not reset it while guessing others
 pub async fn login(
     store: Store,
-    login: Account,
+    limits: LoginLimits,
+    client_ip: Option<IpAddr>,
+    login: LoginRequest,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let email_key = format!("email:{}", login.email.trim().to_lowercase());
+    let mut keys = vec![email_key.clone()];
+    if let Some(ip) = client_ip {
+        keys.push(format!("ip:{}", ip));
+    }
+
+    if let Some(locked_until) = store.get_login_lockout(&keys).await? {
+        let retry_after = (locked_until - Utc::now()).num_seconds() + 1;
+        return Err(warp::reject::custom(
+            handle_errors::Error::TooManyRequests(
+                retry_after.max(1) as u64
+            ),
+        ));
+    }
+
     match store.get_account(login.email).await {
         Ok(account) => {
             match verify_password(
@@ -48,10 +163,17 @@ pub async fn login(
             ) {
                 Ok(verified) => {
                     if verified {
-                        Ok(warp::reply::json(&issue_token(
-                            account.id.expect("id not found"),
-                        )))
+                        store.clear_login_failures(&email_key).await?;
+                        let tokens = start_session(
+                            &store,
+                            account.id,
+                            account.role,
+                        )
+                        .await?;
+                        Ok(warp::reply::json(&tokens))
                     } else {
+                        record_login_failure(&store, &limits, &keys)
+                            .await?;
                         Err(warp::reject::custom(
                             handle_errors::Error::WrongPassword,
                         ))
@@ -62,49 +184,443 @@ pub async fn login(
                 )),
             }
         }
+        Err(handle_errors::Error::DatabaseQueryError(
+            sqlx::Error::RowNotFound,
+        )) => {
+            record_login_failure(&store, &limits, &keys).await?;
+            Err(warp::reject::custom(handle_errors::Error::WrongPassword))
+        }
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Count a failed login for every key and lock the keys out once they
+/// reach the limit
+async fn record_login_failure(
+    store: &Store,
+    limits: &LoginLimits,
+    keys: &[String],
+) -> Result<(), handle_errors::Error> {
+    for key in keys {
+        let failures = store
+            .add_login_failure(key, limits.max_lockout_seconds)
+            .await?;
+        if let Some(seconds) = limits.lockout(failures) {
+            store.lock_login(key, seconds).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete the login attempts which are forgotten anyway in the
+/// background, every `LOGIN_ATTEMPTS_PRUNE_INTERVAL`. A failure only
+/// delays it to the next interval
+pub fn prune_login_attempts(store: Store, limits: LoginLimits) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(LOGIN_ATTEMPTS_PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = store
+                .prune_login_attempts(limits.max_lockout_seconds)
+                .await
+            {
+                tracing::event!(
+                    tracing::Level::WARN,
+                    "login attempts not pruned: {}",
+                    e
+                );
+            }
+        }
+    });
+}
+
+/// Address of the client, taken from the last `X-Forwarded-For` entry
+/// when `trust_forwarded_for` is set and from the connection otherwise
+pub fn client_ip(
+    trust_forwarded_for: bool,
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = warp::Rejection> + Clone
+{
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(
+            move |remote: Option<SocketAddr>,
+                  forwarded: Option<String>| {
+                forwarded
+                    .filter(|_| trust_forwarded_for)
+                    .and_then(|forwarded| forwarded_ip(&forwarded))
+                    .or(remote.map(|remote| remote.ip()))
+            },
+        )
+}
+
+/// Last address of an `X-Forwarded-For` header, the one added by our
+/// proxy. Earlier entries can be set by the client
+fn forwarded_ip(forwarded: &str) -> Option<IpAddr> {
+    forwarded.rsplit(',').next()?.trim().parse().ok()
+}
+
+/// Exchange a refresh token for a new access and refresh token. Every
+/// refresh token can only be used once
+pub async fn refresh(
+    store: Store,
+    request: RefreshRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let refresh_token = generate_token();
+    let (account_id, role, session_id) = store
+        .rotate_refresh_token(
+            &hash_token(&request.refresh_token),
+            &hash_token(&refresh_token),
+            Utc::now()
+                + chrono::Duration::days(REFRESH_TOKEN_LIFETIME_DAYS),
+        )
+        .await?;
+
+    Ok(warp::reply::json(&TokenPair {
+        access_token: issue_token(account_id, role, &session_id),
+        refresh_token,
+        expires_in: ACCESS_TOKEN_LIFETIME_MINUTES * 60,
+    }))
+}
+
+/// Revoke the access token in use and the refresh tokens of its login
+/// session
+pub async fn logout(
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.revoke_session(&session).await {
+        Ok(_) => {
+            Ok(warp::reply::with_status("Logged out", StatusCode::OK))
+        }
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Revoke every access and refresh token of the logged in account
+pub async fn logout_all(
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.revoke_all_sessions(&session.account_id).await {
+        Ok(_) => Ok(warp::reply::with_status(
+            "Logged out of all sessions",
+            StatusCode::OK,
+        )),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Email a single use password reset link. The response is the same
+/// whether the email is registered or not, and the email is sent in the
+/// background so the timing does not tell either
+pub async fn forgot_password(
+    store: Store,
+    notifier: Notifier,
+    request: ForgotPassword,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let token = generate_token();
+    let expires_at = Utc::now()
+        + chrono::Duration::minutes(PASSWORD_RESET_LIFETIME_MINUTES);
+
+    if store
+        .add_password_reset(
+            &request.email,
+            &hash_token(&token),
+            expires_at,
+        )
+        .await?
+    {
+        tokio::spawn(async move {
+            if let Err(e) =
+                notifier.send_password_reset(&request.email, &token).await
+            {
+                tracing::event!(tracing::Level::ERROR, "{}", e);
+            }
+        });
+    }
+
+    Ok(warp::reply::with_status(
+        "If the email is registered, a reset link has been sent",
+        StatusCode::OK,
+    ))
+}
+
+/// Target of the link in the password reset email, a form asking for the
+/// new password which posts it with the token to `/password/reset`
+pub async fn reset_password_form(
+    params: HashMap<String, String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let token = params
+        .get("token")
+        .ok_or(handle_errors::Error::MissingParameters)?;
+    // Tokens are URL safe base64, anything else can not be valid and is
+    // not put into the page
+    if !token
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(warp::reject::custom(
+            handle_errors::Error::InvalidToken,
+        ));
+    }
+
+    Ok(warp::reply::html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Reset password</title></head>
+<body>
+<form method="post" action="/password/reset">
+<input type="hidden" name="token" value="{}">
+<label>New password <input type="password" name="password" required></label>
+<button type="submit">Reset password</button>
+</form>
+</body>
+</html>
+"#,
+        token
+    )))
+}
+
+/// Set a new password with a reset token, which logs the account out of
+/// every session. The token is checked before the password is hashed, so
+/// guessing tokens does not cost a hash each
+pub async fn reset_password(
+    store: Store,
+    reset: PasswordReset,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    validate(vec![("password", check_password(&reset.password))])?;
+    let token_hash = hash_token(&reset.token);
+    if !store.is_password_reset_valid(&token_hash).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::InvalidToken,
+        ));
+    }
+    let hashed_password = hash_password(reset.password.as_bytes());
+
+    match store.reset_password(&token_hash, &hashed_password).await {
+        Ok(_) => {
+            Ok(warp::reply::with_status("Password reset", StatusCode::OK))
+        }
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
 
-fn verify_password(
+pub(crate) fn verify_password(
     hash: &str,
     password: &[u8],
 ) -> Result<bool, argon2::Error> {
     argon2::verify_encoded(hash, password)
 }
 
-fn issue_token(account_id: AccountId) -> String {
+/// Issue the first access and refresh token of a new login session
+pub(crate) async fn start_session(
+    store: &Store,
+    account_id: AccountId,
+    role: Role,
+) -> Result<TokenPair, handle_errors::Error> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let refresh_token = generate_token();
+    store
+        .add_refresh_token(
+            &hash_token(&refresh_token),
+            &session_id,
+            &account_id,
+            Utc::now()
+                + chrono::Duration::days(REFRESH_TOKEN_LIFETIME_DAYS),
+        )
+        .await?;
+
+    Ok(TokenPair {
+        access_token: issue_token(account_id, role, &session_id),
+        refresh_token,
+        expires_in: ACCESS_TOKEN_LIFETIME_MINUTES * 60,
+    })
+}
+
+fn issue_token(
+    account_id: AccountId,
+    role: Role,
+    session_id: &str,
+) -> String {
     let key = env::var("PASETO_KEY").unwrap();
     let current_data_time = Utc::now();
-    let dt = current_data_time + chrono::Duration::days(1);
+    let dt = current_data_time
+        + chrono::Duration::minutes(ACCESS_TOKEN_LIFETIME_MINUTES);
 
     paseto::tokens::PasetoBuilder::new()
         .set_encryption_key(&Vec::from(key.as_bytes()))
         .set_expiration(&dt)
-        .set_not_before(&Utc::now())
+        .set_not_before(&current_data_time)
+        .set_jti(&uuid::Uuid::new_v4().to_string())
         .set_claim("account_id", serde_json::json!(account_id))
+        .set_claim("role", serde_json::json!(role))
+        .set_claim("sid", serde_json::json!(session_id))
         .build()
         .expect("Failed to construct paseto token w/ builder!")
 }
 
+/// Expiring token for links sent by email, bound to `email` and only
+/// valid for `purpose`
+fn issue_email_token(
+    email: &str,
+    purpose: &str,
+    lifetime: chrono::Duration,
+) -> String {
+    let key = env::var("PASETO_KEY").unwrap();
+    let current_data_time = Utc::now();
+
+    paseto::tokens::PasetoBuilder::new()
+        .set_encryption_key(&Vec::from(key.as_bytes()))
+        .set_expiration(&(current_data_time + lifetime))
+        .set_not_before(&current_data_time)
+        .set_claim("email", serde_json::json!(email))
+        .set_claim("purpose", serde_json::json!(purpose))
+        .build()
+        .expect("Failed to construct paseto token w/ builder!")
+}
+
+/// Email address of a token issued by `issue_email_token` for `purpose`
+fn verify_email_token(
+    token: &str,
+    purpose: &str,
+) -> Result<String, handle_errors::Error> {
+    let key = env::var("PASETO_KEY").unwrap();
+    let claims = paseto::tokens::validate_local_token(
+        token,
+        None,
+        key.as_bytes(),
+        &paseto::tokens::TimeBackend::Chrono,
+    )
+    .map_err(|_| handle_errors::Error::InvalidToken)?;
+
+    match (claims["purpose"].as_str(), claims["email"].as_str()) {
+        (Some(claim), Some(email)) if claim == purpose => {
+            Ok(email.to_string())
+        }
+        _ => Err(handle_errors::Error::InvalidToken),
+    }
+}
+
+/// Random opaque token for refresh tokens and password resets, only its
+/// hash is stored
+fn generate_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>())
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Require a valid token in `Authorization: Bearer <token>` or, with
+/// `legacy_header`, in the `auth` header. Rejects with `Unauthorized`
+/// otherwise
 pub fn auth(
+    store: Store,
+    legacy_header: bool,
 ) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
-    warp::header::<String>("auth").and_then(|token: String| {
-        let token = match verify_token(token) {
-            Ok(t) => t,
-            Err(_) => return future::ready(Err(warp::reject::reject())),
-        };
+    token(legacy_header).and_then(move |token: Option<String>| {
+        let store = store.clone();
+        async move {
+            let token = token.ok_or_else(|| {
+                warp::reject::custom(handle_errors::Error::Unauthorized)
+            })?;
+            verify_token(token, &store).await.map_err(|_| {
+                warp::reject::custom(handle_errors::Error::Unauthorized)
+            })
+        }
+    })
+}
 
-        future::ready(Ok(token))
+/// Like `auth()`, but rejects accounts below `min_role` as forbidden
+pub fn require_role(
+    store: Store,
+    legacy_header: bool,
+    min_role: Role,
+) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
+    auth(store, legacy_header).and_then(move |session: Session| {
+        if session.role >= min_role {
+            future::ready(Ok(session))
+        } else {
+            future::ready(Err(warp::reject::custom(
+                handle_errors::Error::Forbidden,
+            )))
+        }
+    })
+}
+
+/// Like `auth()`, but lets anonymous requests through. A token which is
+/// sent has to be valid though
+pub fn optional_auth(
+    store: Store,
+    legacy_header: bool,
+) -> impl Filter<Extract = (Option<Session>,), Error = warp::Rejection> + Clone
+{
+    token(legacy_header).and_then(move |token: Option<String>| {
+        let store = store.clone();
+        async move {
+            match token {
+                None => Ok(None),
+                Some(token) => match verify_token(token, &store).await {
+                    Ok(session) => Ok(Some(session)),
+                    Err(_) => Err(warp::reject::custom(
+                        handle_errors::Error::Unauthorized,
+                    )),
+                },
+            }
+        }
     })
 }
 
-pub fn verify_token(
+/// Extract the token sent by the client, `Authorization` takes precedence
+/// over the legacy `auth` header
+pub(crate) fn token(
+    legacy_header: bool,
+) -> impl Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone
+{
+    warp::header::optional::<String>("authorization")
+        .and(warp::header::optional::<String>("auth"))
+        .map(move |authorization: Option<String>, auth: Option<String>| {
+            match authorization {
+                Some(authorization) => bearer_token(&authorization),
+                None if legacy_header => auth,
+                None => None,
+            }
+        })
+}
+
+/// Token of an `Authorization: Bearer <token>` header value
+fn bearer_token(authorization: &str) -> Option<String> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    let token = token.trim();
+    if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() {
+        Some(token.to_string())
+    } else {
+        None
+    }
+}
+
+/// Decrypt a token and make sure it has not been revoked
+pub async fn verify_token(
     token: String,
+    store: &Store,
+) -> Result<Session, handle_errors::Error> {
+    let session = decrypt_token(&token)?;
+
+    if store.is_token_revoked(&session).await? {
+        return Err(handle_errors::Error::Unauthorized);
+    }
+
+    Ok(session)
+}
+
+/// Decrypt a token without checking whether it has been revoked
+pub(crate) fn decrypt_token(
+    token: &str,
 ) -> Result<Session, handle_errors::Error> {
     let key = env::var("PASETO_KEY").unwrap();
     let token = paseto::tokens::validate_local_token(
-        &token,
+        token,
         None,
         key.as_bytes(),
         &paseto::tokens::TimeBackend::Chrono,
@@ -113,3 +629,279 @@ pub fn verify_token(
     serde_json::from_value::<Session>(token)
         .map_err(|_| handle_errors::Error::CannotDecryptToken)
 }
+
+#[cfg(test)]
+mod auth_tests {
+    use sqlx::postgres::PgPoolOptions;
+    use warp::{http::header::WWW_AUTHENTICATE, Filter, Reply};
+
+    use std::sync::Arc;
+
+    use super::{
+        auth, bearer_token, forwarded_ip, generate_token, hash_token,
+        issue_email_token, login, register, reset_password_form,
+        verify_email_token,
+    };
+    use crate::{
+        config::LoginLimits,
+        mailer::{FileMailer, Notifier},
+        routes::{
+            account::{change_password, get_accounts, get_me},
+            user::get_user,
+        },
+        store::Store,
+    };
+
+    fn lazy_store() -> Store {
+        Store {
+            connection: PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/unused")
+                .unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_token_is_unauthorized() {
+        let filter = auth(lazy_store(), false)
+            .map(|_| "ok")
+            .recover(handle_errors::return_error);
+
+        let response = warp::test::request()
+            .header("auth", "legacy-token")
+            .filter(&filter)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn invalid_registration_is_rejected_before_storing() {
+        let notifier = Notifier::new(
+            Arc::new(FileMailer::new(std::env::temp_dir())),
+            "http://localhost:8080",
+        );
+        let filter = warp::any()
+            .map(lazy_store)
+            .and(warp::any().map(move || notifier.clone()))
+            .and(warp::body::json())
+            .and_then(register)
+            .recover(handle_errors::return_error);
+
+        let response = warp::test::request()
+            .method("POST")
+            .json(&serde_json::json!({
+                "email": "test@email.com",
+                "password": "password"
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 400);
+        let body: serde_json::Value =
+            serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["errors"][0]["field"], "password");
+    }
+
+    /// Fail if a response body carries a password or its hash
+    fn assert_no_password(body: &[u8]) -> serde_json::Value {
+        let body = std::str::from_utf8(body).unwrap();
+        assert!(!body.contains("$argon2"), "hash in {}", body);
+        assert!(!body.contains("password"), "password in {}", body);
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database in TEST_DATABASE_URL"]
+    async fn responses_never_contain_password_hash() {
+        std::env::set_var(
+            "PASETO_KEY",
+            "RANDOM WORDS WINTER MACINTOSH PC",
+        );
+        let store =
+            Store::new(&std::env::var("TEST_DATABASE_URL").unwrap())
+                .await
+                .unwrap();
+        sqlx::migrate!().run(&store.connection).await.unwrap();
+        let store_filter = {
+            let store = store.clone();
+            warp::any().map(move || store.clone())
+        };
+        let notifier = Notifier::new(
+            Arc::new(FileMailer::new(std::env::temp_dir())),
+            "http://localhost:8080",
+        );
+        let email = format!("{}@email.com", uuid::Uuid::new_v4());
+
+        let filter = store_filter
+            .clone()
+            .and(warp::any().map(move || notifier.clone()))
+            .and(warp::body::json())
+            .and_then(register)
+            .recover(handle_errors::return_error);
+        let response = warp::test::request()
+            .method("POST")
+            .json(&serde_json::json!({
+                "email": email,
+                "password": "correct horse 9"
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 201);
+        let account_id = assert_no_password(response.body())["id"].clone();
+
+        let limits = LoginLimits {
+            max_failures: 5,
+            lockout_seconds: 30,
+            max_lockout_seconds: 3600,
+        };
+        let filter = store_filter
+            .clone()
+            .and(warp::any().map(move || limits))
+            .and(warp::any().map(|| None))
+            .and(warp::body::json())
+            .and_then(login)
+            .recover(handle_errors::return_error);
+        let response = warp::test::request()
+            .method("POST")
+            .json(&serde_json::json!({
+                "email": email,
+                "password": "correct horse 9"
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        let tokens = assert_no_password(response.body());
+        let bearer =
+            format!("Bearer {}", tokens["access_token"].as_str().unwrap());
+
+        let filter = auth(store.clone(), false)
+            .and(store_filter.clone())
+            .and_then(get_me)
+            .recover(handle_errors::return_error);
+        let response = warp::test::request()
+            .header("authorization", &bearer)
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_no_password(response.body());
+
+        let filter = auth(store.clone(), false)
+            .and(store_filter.clone())
+            .and_then(get_accounts)
+            .recover(handle_errors::return_error);
+        let response = warp::test::request()
+            .header("authorization", &bearer)
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_no_password(response.body());
+
+        let filter = warp::any()
+            .map(move || account_id.as_i64().unwrap() as i32)
+            .and(store_filter.clone())
+            .and_then(get_user)
+            .recover(handle_errors::return_error);
+        let response = warp::test::request().reply(&filter).await;
+        assert_eq!(response.status(), 200);
+        assert_no_password(response.body());
+
+        let filter = auth(store.clone(), false)
+            .and(store_filter)
+            .and(warp::body::json())
+            .and_then(change_password)
+            .recover(handle_errors::return_error);
+        let response = warp::test::request()
+            .method("PUT")
+            .header("authorization", &bearer)
+            .json(&serde_json::json!({
+                "current_password": "correct horse 9",
+                "new_password": "battery staple 9"
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_no_password(response.body());
+    }
+
+    #[tokio::test]
+    async fn reset_link_serves_a_form() {
+        let filter = warp::query()
+            .and_then(reset_password_form)
+            .recover(handle_errors::return_error);
+
+        let response = warp::test::request()
+            .path("/?token=abc-_9")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 200);
+        let body = std::str::from_utf8(response.body()).unwrap();
+        assert!(body.contains(r#"action="/password/reset""#));
+        assert!(body.contains(r#"name="token" value="abc-_9""#));
+
+        let response = warp::test::request()
+            .path("/?token=%22%3E%3Cscript%3E")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), 400);
+
+        let response = warp::test::request().reply(&filter).await;
+        assert_eq!(response.status(), 422);
+    }
+
+    #[test]
+    fn email_token_is_bound_to_purpose() {
+        std::env::set_var(
+            "PASETO_KEY",
+            "RANDOM WORDS WINTER MACINTOSH PC",
+        );
+        let token = issue_email_token(
+            "test@email.com",
+            "verify_email",
+            chrono::Duration::hours(1),
+        );
+
+        assert_eq!(
+            verify_email_token(&token, "verify_email").unwrap(),
+            "test@email.com"
+        );
+        assert!(verify_email_token(&token, "reset_password").is_err());
+        assert!(verify_email_token("v2.local.garbage", "verify_email")
+            .is_err());
+    }
+
+    #[test]
+    fn last_forwarded_address() {
+        assert_eq!(
+            forwarded_ip("10.0.0.1, 203.0.113.7"),
+            "203.0.113.7".parse().ok()
+        );
+        assert_eq!(
+            forwarded_ip("2001:db8::1"),
+            "2001:db8::1".parse().ok()
+        );
+        assert_eq!(forwarded_ip("10.0.0.1, unknown"), None);
+    }
+
+    #[test]
+    fn bearer_authorization() {
+        assert_eq!(bearer_token("Bearer abc"), Some("abc".to_string()));
+        assert_eq!(bearer_token("bearer  abc "), Some("abc".to_string()));
+        assert_eq!(bearer_token("Basic abc"), None);
+        assert_eq!(bearer_token("Bearer"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+    }
+
+    #[test]
+    fn tokens_are_unique() {
+        assert_ne!(generate_token(), generate_token());
+    }
+
+    #[test]
+    fn token_hash_is_stable() {
+        let token = generate_token();
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), token);
+        assert_eq!(hash_token(&token).len(), 64);
+    }
+}
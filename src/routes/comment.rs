@@ -0,0 +1,154 @@
+use warp::hyper::StatusCode;
+
+use crate::{
+    profanity::check_profanity,
+    store::Store,
+    types::{
+        account::Session,
+        answer::AnswerId,
+        comment::{CommentTarget, NewComment},
+        question::QuestionId,
+    },
+};
+
+/// Return every comment on a question
+pub async fn get_question_comments(
+    question_id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !store.question_exists(question_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+
+    let target = CommentTarget::Question(QuestionId(question_id));
+    match store.get_comments(&target).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Return every comment on an answer
+pub async fn get_answer_comments(
+    answer_id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.get_answer(answer_id).await?;
+
+    let target = CommentTarget::Answer(AnswerId(answer_id));
+    match store.get_comments(&target).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Add a comment to a question
+pub async fn add_question_comment(
+    question_id: i32,
+    session: Session,
+    store: Store,
+    new_comment: NewComment,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !store.question_exists(question_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+
+    let target = CommentTarget::Question(QuestionId(question_id));
+    add_comment(target, session, store, new_comment).await
+}
+
+/// Add a comment to an answer
+pub async fn add_answer_comment(
+    answer_id: i32,
+    session: Session,
+    store: Store,
+    new_comment: NewComment,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.get_answer(answer_id).await?;
+
+    let target = CommentTarget::Answer(AnswerId(answer_id));
+    add_comment(target, session, store, new_comment).await
+}
+
+async fn add_comment(
+    target: CommentTarget,
+    session: Session,
+    store: Store,
+    new_comment: NewComment,
+) -> Result<warp::reply::WithStatus<&'static str>, warp::Rejection> {
+    let content = match check_profanity(new_comment.content).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+    let comment = NewComment { content };
+
+    match store
+        .add_comment(&target, comment, session.account_id)
+        .await
+    {
+        Ok(_) => {
+            Ok(warp::reply::with_status("Comment added", StatusCode::OK))
+        }
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Update a comment owned by the logged in account
+pub async fn update_comment(
+    id: i32,
+    session: Session,
+    store: Store,
+    comment: NewComment,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    store.get_comment(id).await?;
+
+    if !store.is_comment_owner(id, &account_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::Forbidden,
+        ));
+    }
+
+    let content = match check_profanity(comment.content).await {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+    let comment = NewComment { content };
+
+    match store.update_comment(comment, id, account_id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Delete a comment owned by the logged in account
+pub async fn delete_comment(
+    id: i32,
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    store.get_comment(id).await?;
+
+    if !store.is_comment_owner(id, &account_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::Forbidden,
+        ));
+    }
+
+    match store.delete_comment(id, account_id).await {
+        Ok(true) => Ok(warp::reply::with_status(
+            format!("Comment {} deleted", id),
+            StatusCode::OK,
+        )),
+        Ok(false) => Err(warp::reject::custom(
+            handle_errors::Error::CommentNotFound,
+        )),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
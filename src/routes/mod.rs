@@ -1,6 +1,16 @@
-///
+/// Handlers for the account management routes
+pub mod account;
+/// Handlers for the answer routes
 pub mod answer;
-///
+/// Handlers for the comment routes
+pub mod comment;
+/// Handlers for the question routes
 pub mod question;
+/// Handlers for the tag routes
+pub mod tag;
+/// Handlers for the public user profile routes
+pub mod user;
+/// Handlers for voting on questions and answers
+pub mod vote;
 
 pub mod auth;
\ No newline at end of file
@@ -1,24 +1,140 @@
 use std::collections::HashMap;
 
 use tracing::{event, instrument, Level};
-use warp::hyper::StatusCode;
+use warp::{
+    http::{
+        header::{ETAG, LINK},
+        HeaderValue,
+    },
+    hyper::StatusCode,
+    Reply,
+};
 
 use crate::{
     profanity::check_profanity,
     store::Store,
     types::{
-        pagination::{extract_pagination, Pagination},
-        question::{NewQuestion, Question}, account::Session,
+        filter::extract_question_query,
+        pagination::{
+            extract_envelope, extract_pagination, CursorPage, Pagination,
+        },
+        question::{
+            if_match_matches, NewQuestion, QuestionId, QuestionPatch,
+            UpdatedQuestion,
+        },
+        account::{Role, Session},
+        tag::normalize_tags,
     },
 };
 
-///
+/// Return a (optionally paginated) list of questions, filtered and
+/// sorted by the query params. Deleted questions are hidden unless an
+/// admin asks for `include_deleted=true`. With `envelope=true` the
+/// questions are wrapped in a `Page` with the total count. With `cursor`
+/// pagination they are wrapped in a `CursorPage` and a `Link` header
+/// points to the next page
 #[instrument]
 pub async fn get_questions(
-    params: HashMap<String, String>,
+    mut params: Vec<(String, String)>,
+    session: Option<Session>,
     store: Store,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     event!(target: "rest_api", Level::INFO, "querying questions");
+    let envelope = extract_envelope(&mut params)?;
+    let (filter, pagination) = extract_question_query(params.clone())?;
+
+    if filter.include_deleted {
+        match session {
+            Some(session) if session.role == Role::Admin => (),
+            Some(_) => {
+                return Err(warp::reject::custom(
+                    handle_errors::Error::Forbidden,
+                ))
+            }
+            None => {
+                return Err(warp::reject::custom(
+                    handle_errors::Error::Unauthorized,
+                ))
+            }
+        }
+    }
+    let is_cursor = matches!(pagination, Pagination::Cursor { .. });
+
+    if envelope {
+        let (limit, offset) = pagination.into_offset()?;
+        return match store.get_questions_page(filter, limit, offset).await
+        {
+            Ok(page) => Ok(with_total_count(
+                warp::reply::json(&page).into_response(),
+                page.total,
+            )),
+            Err(e) => Err(warp::reject::custom(e)),
+        };
+    }
+
+    let (questions, next_cursor) =
+        match store.get_questions(filter, pagination).await {
+            Ok(res) => res,
+            Err(e) => return Err(warp::reject::custom(e)),
+        };
+
+    if !is_cursor {
+        return Ok(warp::reply::json(&questions).into_response());
+    }
+
+    let next_cursor = next_cursor.map(|cursor| cursor.encode());
+    let link = next_cursor.as_ref().map(|cursor| {
+        let mut params: Vec<(String, String)> = params
+            .into_iter()
+            .filter(|(key, _)| key != "cursor")
+            .collect();
+        params.push(("cursor".to_string(), cursor.clone()));
+        format!(
+            "</questions?{}>; rel=\"next\"",
+            serde_urlencoded::to_string(params).unwrap_or_default()
+        )
+    });
+
+    let mut res = warp::reply::json(&CursorPage {
+        items: questions,
+        next_cursor,
+    })
+    .into_response();
+    if let Some(link) = link {
+        if let Ok(value) = HeaderValue::from_str(&link) {
+            res.headers_mut().insert(LINK, value);
+        }
+    }
+    Ok(res)
+}
+
+/// Add the `X-Total-Count` header to a list response
+fn with_total_count(
+    mut res: warp::reply::Response,
+    total: i64,
+) -> warp::reply::Response {
+    res.headers_mut()
+        .insert("x-total-count", HeaderValue::from(total));
+    res
+}
+
+/// Return the questions matching the full-text search query `q`, most
+/// relevant first. Supports the same `limit`/`offset` pagination as
+/// `get_questions`
+#[instrument]
+pub async fn search_questions(
+    mut params: HashMap<String, String>,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    event!(target: "rest_api", Level::INFO, "searching questions");
+    let query = match params.remove("q") {
+        Some(q) if !q.trim().is_empty() => q,
+        _ => {
+            return Err(warp::reject::custom(
+                handle_errors::Error::MissingParameters,
+            ))
+        }
+    };
     let mut pagination = Pagination::default();
 
     if !params.is_empty() {
@@ -26,16 +142,57 @@ pub async fn get_questions(
         pagination = extract_pagination(params)?;
     }
 
-    match store
-        .get_questions(pagination.limit, pagination.offset)
-        .await
+    let (limit, offset) = pagination.into_offset()?;
+
+    match store.search_questions(&query, limit, offset).await
     {
         Ok(res) => Ok(warp::reply::json(&res)),
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
 
-///
+/// Return a single question with its answers embedded
+#[instrument]
+pub async fn get_question(
+    id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    event!(target: "rest_api", Level::INFO, "querying question {}", id);
+
+    match store.get_question(id).await {
+        Ok(res) => {
+            let etag = res.etag();
+            Ok(with_etag(warp::reply::json(&res), etag))
+        }
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Attach an `ETag` to a reply
+fn with_etag(reply: impl Reply, etag: String) -> impl Reply {
+    warp::reply::with_header(reply, ETAG, etag)
+}
+
+/// Compare `If-Match` with the `ETag` of the current question. Returns
+/// the version an update has to be applied to, `None` for `*`
+async fn if_match_version(
+    store: &Store,
+    id: i32,
+    if_match: &str,
+) -> Result<Option<i32>, handle_errors::Error> {
+    if if_match.trim() == "*" {
+        return Ok(None);
+    }
+    let current = store.get_question(id).await?;
+    if if_match_matches(if_match, &current.etag()) {
+        Ok(Some(current.question.version))
+    } else {
+        Err(handle_errors::Error::PreconditionFailed)
+    }
+}
+
+/// Add a new question owned by the logged in account, which needs a
+/// verified email address
 pub async fn add_question(
     session: Session,
     store: Store,
@@ -43,23 +200,31 @@ pub async fn add_question(
 ) -> Result<impl warp::Reply, warp::Rejection> {
 
     let account_id = session.account_id;
+    if !store.get_account_by_id(&account_id).await?.email_verified {
+        return Err(warp::reject::custom(
+            handle_errors::Error::EmailNotVerified,
+        ));
+    }
+    let tags = normalize_tags(new_question.tags)?;
 
     let title = check_profanity(new_question.title);
     let content = check_profanity(new_question.content);
 
     let (title, content) = tokio::join!(title, content);
 
-    if title.is_err() {
-        return Err(warp::reject::custom(title.unwrap_err()));
-    }
-    if content.is_err() {
-        return Err(warp::reject::custom(title.unwrap_err()));
-    }
+    let title = match title {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+    let content = match content {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
 
     let question = NewQuestion {
-        title: title.unwrap(),
-        content: content.unwrap(),
-        tags: new_question.tags,
+        title,
+        content,
+        tags,
     };
 
     match store.add_question(question, account_id).await {
@@ -71,57 +236,295 @@ pub async fn add_question(
 }
 
 
-///
+/// Update a question owned by the logged in account, moderators can
+/// update any question. With an `If-Match`
+/// header the update is only applied if the `ETag` still matches. An `id`
+/// in the body has to match the path
 pub async fn update_question(
     id: i32,
     session: Session,
+    if_match: Option<String>,
     store: Store,
-    question: Question,
+    question: UpdatedQuestion,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let account_id = session.account_id;
+    let expected_version = match if_match {
+        Some(if_match) => if_match_version(&store, id, &if_match).await?,
+        None => None,
+    };
+
+    if let Some(QuestionId(body_id)) = question.id {
+        if body_id != id {
+            return Err(warp::reject::custom(
+                handle_errors::Error::InvalidParameter(format!(
+                    "id={}",
+                    body_id
+                )),
+            ));
+        }
+    }
+
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
 
-    if store.is_question_owner(id, &account_id).await? {
+    if session.role >= Role::Moderator
+        || store.is_question_owner(id, &account_id).await?
+    {
 
+    let tags = normalize_tags(question.tags)?;
     let title = check_profanity(question.title);
     let content = check_profanity(question.content);
 
     let (title, content) = tokio::join!(title, content);
 
-    if title.is_err() {
-        return Err(warp::reject::custom(title.unwrap_err()));
-    }
-    if content.is_err() {
-        return Err(warp::reject::custom(title.unwrap_err()));
-    }
-    let question = Question {
-        id: question.id,
-        title: title.unwrap(),
-        content: content.unwrap(),
-        tags: question.tags,
+    let title = match title {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
     };
-    match store.update_question(question, id, account_id).await {
-        Ok(res) => Ok(warp::reply::json(&res)),
+    let content = match content {
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+    let question = UpdatedQuestion {
+        id: Some(QuestionId(id)),
+        title,
+        content,
+        tags,
+    };
+    match store
+        .update_question(question, id, account_id, expected_version)
+        .await
+    {
+        Ok(res) => {
+            // The tag has to match the representation of `get_question`
+            let etag = store.get_question(id).await?.etag();
+            Ok(with_etag(warp::reply::json(&res), etag))
+        }
         Err(e) => Err(warp::reject::custom(e)),
     }
 
     } else {
         Err(warp::reject::custom(
-            handle_errors::Error::Unauthorized
+            handle_errors::Error::Forbidden
         ))
     }
 }
 
-///
+/// Partially update a question owned by the logged in account or, for
+/// moderators, any question. Only the given fields are checked for
+/// profanity and written. Honours
+/// `If-Match` like `update_question`
+pub async fn patch_question(
+    id: i32,
+    session: Session,
+    if_match: Option<String>,
+    store: Store,
+    patch: QuestionPatch,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+    let expected_version = match if_match {
+        Some(if_match) => if_match_version(&store, id, &if_match).await?,
+        None => None,
+    };
+
+    if patch.is_empty() {
+        return Err(warp::reject::custom(
+            handle_errors::Error::MissingParameters,
+        ));
+    }
+
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+
+    if session.role < Role::Moderator
+        && !store.is_question_owner(id, &account_id).await?
+    {
+        return Err(warp::reject::custom(handle_errors::Error::Forbidden));
+    }
+
+    let tags = normalize_tags(patch.tags)?;
+    let title = async {
+        match patch.title {
+            Some(title) => check_profanity(title).await.map(Some),
+            None => Ok(None),
+        }
+    };
+    let content = async {
+        match patch.content {
+            Some(content) => check_profanity(content).await.map(Some),
+            None => Ok(None),
+        }
+    };
+
+    let (title, content) = tokio::join!(title, content);
+
+    let patch = QuestionPatch {
+        title: title.map_err(warp::reject::custom)?,
+        content: content.map_err(warp::reject::custom)?,
+        tags,
+    };
+    match store
+        .patch_question(patch, id, account_id, expected_version)
+        .await
+    {
+        Ok(res) => {
+            // The tag has to match the representation of `get_question`
+            let etag = store.get_question(id).await?.etag();
+            Ok(with_etag(warp::reply::json(&res), etag))
+        }
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Return the edit history of a question, oldest revision first
+pub async fn get_question_revisions(
+    id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+
+    match store.get_question_revisions(id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Roll a question owned by the logged in account or, for moderators,
+/// any question back to an earlier revision
+pub async fn restore_question_revision(
+    id: i32,
+    revision: i32,
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+    if session.role < Role::Moderator
+        && !store.is_question_owner(id, &account_id).await?
+    {
+        return Err(warp::reject::custom(handle_errors::Error::Forbidden));
+    }
+
+    match store
+        .restore_question_revision(id, revision, account_id)
+        .await
+    {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Mark an answer as the accepted solution of a question owned by the
+/// logged in account
+pub async fn accept_answer(
+    id: i32,
+    answer_id: i32,
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+    if !store.is_question_owner(id, &account_id).await? {
+        return Err(warp::reject::custom(handle_errors::Error::Forbidden));
+    }
+    if store.get_answer(answer_id).await?.question_id.0 != id {
+        return Err(warp::reject::custom(
+            handle_errors::Error::AnswerOfOtherQuestion,
+        ));
+    }
+
+    match store.accept_answer(id, Some(answer_id), account_id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Remove the accepted answer from a question owned by the logged in
+/// account
+pub async fn unaccept_answer(
+    id: i32,
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+    if !store.is_question_owner(id, &account_id).await? {
+        return Err(warp::reject::custom(handle_errors::Error::Forbidden));
+    }
+
+    match store.accept_answer(id, None, account_id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Delete a question owned by the logged in account, moderators can
+/// delete any question. The question is only hidden and can be restored
+/// by a moderator
 pub async fn delete_question(
     id: i32,
-    _session: Session,
+    session: Session,
     store: Store,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+
+    if session.role < Role::Moderator
+        && !store.is_question_owner(id, &account_id).await?
+    {
+        return Err(warp::reject::custom(handle_errors::Error::Forbidden));
+    }
+
     match store.delete_question(id).await {
-        Ok(_) => Ok(warp::reply::with_status(
+        Ok(true) => Ok(warp::reply::with_status(
             format!("Question {} deleted", id),
             StatusCode::OK,
         )),
+        Ok(false) => Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        )),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Restore a deleted question, only allowed for moderators and admins
+pub async fn restore_question(
+    id: i32,
+    _session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.restore_question(id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
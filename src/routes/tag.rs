@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use percent_encoding::percent_decode_str;
+use tracing::{event, instrument, Level};
+use warp::hyper::StatusCode;
+
+use crate::{
+    store::Store,
+    types::{
+        account::Session,
+        pagination::{extract_pagination, Pagination},
+        tag::{
+            normalize_tag, normalize_tag_description, NewTagSynonym,
+            TagUpdate,
+        },
+    },
+};
+
+/// Return the known tags with their usage counts, most used first.
+/// `prefix` narrows the list down for autocompletion and the same
+/// `limit`/`offset` pagination as for questions is supported
+#[instrument]
+pub async fn get_tags(
+    mut params: HashMap<String, String>,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    event!(target: "rest_api", Level::INFO, "querying tags");
+    let prefix = params
+        .remove("prefix")
+        .map(|prefix| prefix.trim().to_lowercase())
+        .unwrap_or_default();
+    let mut pagination = Pagination::default();
+
+    if !params.is_empty() {
+        event!(Level::INFO, pagination = true);
+        pagination = extract_pagination(params)?;
+    }
+    let (limit, offset) = pagination.into_offset()?;
+
+    match store.get_tags(&prefix, limit, offset).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Set the description of a tag, only allowed for moderators
+pub async fn update_tag(
+    name: String,
+    _session: Session,
+    store: Store,
+    update: TagUpdate,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let name = tag_from_path(&name)?;
+    let description = normalize_tag_description(update.description)?;
+
+    store.set_tag_description(&name, description).await?;
+    match store.get_tag(&name).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Merge a tag into another one as its synonym, only allowed for
+/// moderators. Questions carrying the synonym get the tag instead and
+/// later writes replace the synonym by the tag
+pub async fn add_tag_synonym(
+    name: String,
+    _session: Session,
+    store: Store,
+    new_synonym: NewTagSynonym,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let name = tag_from_path(&name)?;
+    let synonym = normalize_tag(&new_synonym.synonym)?;
+    if synonym == name {
+        return Err(warp::reject::custom(
+            handle_errors::Error::InvalidTags(format!(
+                "\"{}\" cannot be a synonym of itself",
+                name
+            )),
+        ));
+    }
+
+    store.get_tag(&name).await?;
+    match store.get_synonym_tag(&synonym).await? {
+        Some(tag) if tag == name => {}
+        Some(tag) => {
+            return Err(warp::reject::custom(
+                handle_errors::Error::InvalidTags(format!(
+                    "\"{}\" is already a synonym of \"{}\"",
+                    synonym, tag
+                )),
+            ))
+        }
+        None => store.add_tag_synonym(&name, &synonym).await?,
+    }
+
+    match store.get_tag(&name).await {
+        Ok(res) => Ok(warp::reply::with_status(
+            warp::reply::json(&res),
+            StatusCode::CREATED,
+        )),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Decode and normalize a tag taken from the path
+fn tag_from_path(name: &str) -> Result<String, handle_errors::Error> {
+    let name = percent_decode_str(name).decode_utf8().map_err(|_| {
+        handle_errors::Error::InvalidParameter(format!("tag={}", name))
+    })?;
+    normalize_tag(&name)
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use tracing::{event, instrument, Level};
+
+use crate::{
+    store::Store,
+    types::{
+        account::AccountId,
+        pagination::{extract_pagination, Pagination},
+    },
+};
+
+/// Return the public profile of an account
+pub async fn get_user(
+    id: i32,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_public_user(&AccountId(id)).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Return the questions asked by an account, newest first. Supports the
+/// same `limit`/`offset` pagination as `get_questions`
+#[instrument]
+pub async fn get_user_questions(
+    id: i32,
+    params: HashMap<String, String>,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    event!(target: "rest_api", Level::INFO, "querying user questions");
+    let account_id = AccountId(id);
+    let (limit, offset) = user_pagination(params)?;
+    if !store.account_exists(&account_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::AccountNotFound,
+        ));
+    }
+
+    match store.get_user_questions(&account_id, limit, offset).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Return the answers given by an account, newest first. Supports the
+/// same `limit`/`offset` pagination as `get_questions`
+#[instrument]
+pub async fn get_user_answers(
+    id: i32,
+    params: HashMap<String, String>,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    event!(target: "rest_api", Level::INFO, "querying user answers");
+    let account_id = AccountId(id);
+    let (limit, offset) = user_pagination(params)?;
+    if !store.account_exists(&account_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::AccountNotFound,
+        ));
+    }
+
+    match store.get_user_answers(&account_id, limit, offset).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+fn user_pagination(
+    params: HashMap<String, String>,
+) -> Result<(Option<i32>, i32), handle_errors::Error> {
+    let mut pagination = Pagination::default();
+    if !params.is_empty() {
+        event!(Level::INFO, pagination = true);
+        pagination = extract_pagination(params)?;
+    }
+    pagination.into_offset()
+}
@@ -0,0 +1,93 @@
+use crate::{
+    store::Store,
+    types::{
+        account::Session,
+        answer::AnswerId,
+        question::QuestionId,
+        vote::{NewVote, VoteTarget},
+    },
+};
+
+/// Up- or downvote a question of another account
+pub async fn vote_question(
+    id: i32,
+    session: Session,
+    store: Store,
+    vote: NewVote,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+    if store.is_question_owner(id, &account_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::CannotVoteOwnPost,
+        ));
+    }
+
+    let target = VoteTarget::Question(QuestionId(id));
+    match store.add_vote(&target, account_id, vote.direction).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Remove the vote of the logged in account from a question
+pub async fn unvote_question(
+    id: i32,
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !store.question_exists(id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::QuestionNotFound,
+        ));
+    }
+
+    let target = VoteTarget::Question(QuestionId(id));
+    match store.delete_vote(&target, session.account_id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Up- or downvote an answer of another account
+pub async fn vote_answer(
+    id: i32,
+    session: Session,
+    store: Store,
+    vote: NewVote,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    store.get_answer(id).await?;
+    if store.is_answer_owner(id, &account_id).await? {
+        return Err(warp::reject::custom(
+            handle_errors::Error::CannotVoteOwnPost,
+        ));
+    }
+
+    let target = VoteTarget::Answer(AnswerId(id));
+    match store.add_vote(&target, account_id, vote.direction).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Remove the vote of the logged in account from an answer
+pub async fn unvote_answer(
+    id: i32,
+    session: Session,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.get_answer(id).await?;
+
+    let target = VoteTarget::Answer(AnswerId(id));
+    match store.delete_vote(&target, session.account_id).await {
+        Ok(res) => Ok(warp::reply::json(&res)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
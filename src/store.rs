@@ -1,15 +1,397 @@
+use chrono::{DateTime, Utc};
 use handle_errors::Error;
 use sqlx::{
     postgres::{PgPoolOptions, PgRow},
-    PgPool, Row,
+    PgPool, Postgres, QueryBuilder, Row, Transaction,
 };
 
 use crate::types::{
-    account::{Account, AccountId},
-    answer::{Answer, AnswerId, NewAnswer},
-    question::{NewQuestion, Question, QuestionId},
+    account::{Account, AccountId, AccountSummary, Role, Session},
+    answer::{Answer, AnswerId, NewAnswer, UpdatedAnswer},
+    comment::{Comment, CommentId, CommentTarget, NewComment},
+    filter::{QuestionFilter, QuestionSort, TagMatch},
+    pagination::{Cursor, Page, Pagination},
+    question::{
+        NewQuestion, Question, QuestionId, QuestionPatch,
+        QuestionWithAnswers, UpdatedQuestion,
+    },
+    revision::{AnswerRevision, QuestionRevision},
+    search::{SearchResult, Snippets},
+    tag::Tag,
+    user::{reputation, PublicUser},
+    vote::{Score, VoteDirection, VoteTarget},
 };
 
+/// `ts_headline` options used for the search snippets. The text is HTML
+/// escaped before, so `<mark>` is the only markup in a snippet
+const HEADLINE_OPTIONS: &str =
+    "StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=30";
+
+/// Build the `SELECT` for a page of questions matching `filter`
+fn questions_query(
+    filter: &QuestionFilter,
+    pagination: &Pagination,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT q.id, q.title, q.content, q.tags, q.score,
+            q.accepted_answer_id, q.deleted_at, q.version, q.created_on
+        FROM questions q
+        WHERE true",
+    );
+    push_question_conditions(&mut query, filter);
+
+    if let Pagination::Cursor {
+        after: Some(after), ..
+    } = pagination
+    {
+        match filter.sort {
+            QuestionSort::Newest => {
+                query.push(" AND (q.created_on, q.id) < (")
+            }
+            _ => query.push(" AND (q.created_on, q.id) > ("),
+        };
+        query
+            .push_bind(after.created_on)
+            .push(", ")
+            .push_bind(after.id)
+            .push(")");
+    }
+
+    query.push(match filter.sort {
+        QuestionSort::Oldest => " ORDER BY q.created_on, q.id",
+        QuestionSort::Newest => " ORDER BY q.created_on DESC, q.id DESC",
+        QuestionSort::MostAnswered => {
+            " ORDER BY (SELECT count(*) FROM answers a
+                WHERE a.corresponding_question = q.id) DESC,
+                q.created_on DESC, q.id DESC"
+        }
+        QuestionSort::Score => {
+            " ORDER BY q.score DESC, q.created_on DESC, q.id DESC"
+        }
+    });
+    match *pagination {
+        Pagination::Offset { limit, offset } => {
+            query
+                .push(" LIMIT ")
+                .push_bind(limit)
+                .push(" OFFSET ")
+                .push_bind(offset);
+        }
+        // One more row than requested tells whether a next page exists
+        Pagination::Cursor { limit, .. } => {
+            query.push(" LIMIT ").push_bind(limit.saturating_add(1));
+        }
+    }
+
+    query
+}
+
+/// Append the `WHERE` conditions of `filter` to a query over
+/// `questions q`
+fn push_question_conditions(
+    query: &mut QueryBuilder<Postgres>,
+    filter: &QuestionFilter,
+) {
+    if !filter.tags.is_empty() {
+        match filter.tag_match {
+            TagMatch::All => query.push(" AND q.tags @> "),
+            TagMatch::Any => query.push(" AND q.tags && "),
+        };
+        // Synonyms match the questions carrying their tag
+        query
+            .push(
+                "ARRAY(SELECT coalesce(s.tag_name, t.name)
+                FROM unnest(",
+            )
+            .push_bind(filter.tags.clone())
+            .push(
+                "::text[]) AS t(name)
+                LEFT JOIN tag_synonyms s ON s.synonym = t.name)",
+            );
+    }
+    if let Some(author) = &filter.author {
+        query.push(" AND q.account_id = ").push_bind(author.0);
+    }
+    if let Some(created_after) = filter.created_after {
+        query.push(" AND q.created_on > ").push_bind(created_after);
+    }
+    if let Some(created_before) = filter.created_before {
+        query.push(" AND q.created_on < ").push_bind(created_before);
+    }
+    if !filter.include_deleted {
+        query.push(" AND q.deleted_at IS NULL");
+    }
+    if filter.unanswered {
+        query.push(
+            " AND NOT EXISTS (SELECT 1 FROM answers a
+                WHERE a.corresponding_question = q.id)",
+        );
+    }
+}
+
+/// Overwrite title, content and tags of a question and bump its
+/// version. Nothing is updated if the question is deleted or if
+/// `expected_version` is given and no longer matches
+async fn update_question_row(
+    tx: &mut Transaction<'_, Postgres>,
+    title: String,
+    content: String,
+    tags: Option<Vec<String>>,
+    question_id: i32,
+    expected_version: Option<i32>,
+) -> Result<Option<Question>, sqlx::Error> {
+    let tags = resolve_tags(tx, tags).await?;
+    sqlx::query(
+        "UPDATE questions
+        SET title = $1, content = $2, tags = $3, version = version + 1
+        WHERE id = $4 and deleted_at IS NULL
+            and ($5::int IS NULL OR version = $5)
+        RETURNING id, title, content, tags, score, accepted_answer_id,
+            deleted_at, version",
+    )
+    .bind(title)
+    .bind(content)
+    .bind(tags)
+    .bind(question_id)
+    .bind(expected_version)
+    .map(|row: PgRow| question_from_row(&row))
+    .fetch_optional(&mut *tx)
+    .await
+}
+
+/// Replace synonyms by their tag and register tags which are not known
+/// yet, keeping the order of `tags`
+async fn resolve_tags(
+    tx: &mut Transaction<'_, Postgres>,
+    tags: Option<Vec<String>>,
+) -> Result<Option<Vec<String>>, sqlx::Error> {
+    let tags = match tags {
+        Some(tags) if !tags.is_empty() => tags,
+        tags => return Ok(tags),
+    };
+
+    let resolved: Vec<String> = sqlx::query(
+        "SELECT coalesce(s.tag_name, t.name) AS name
+        FROM unnest($1::text[]) WITH ORDINALITY AS t(name, position)
+        LEFT JOIN tag_synonyms s ON s.synonym = t.name
+        ORDER BY t.position",
+    )
+    .bind(tags)
+    .map(|row: PgRow| row.get("name"))
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut tags: Vec<String> = Vec::with_capacity(resolved.len());
+    for tag in resolved {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    sqlx::query(
+        "INSERT INTO tags (name)
+        SELECT unnest($1::text[])
+        ON CONFLICT DO NOTHING",
+    )
+    .bind(&tags)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(Some(tags))
+}
+
+/// Record the current state of `question` as its newest revision
+async fn add_question_revision(
+    tx: &mut Transaction<'_, Postgres>,
+    question: &Question,
+    editor_id: &AccountId,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO question_revisions
+            (question_id, revision, title, content, tags, editor_id)
+        SELECT $1, coalesce(max(revision), 0) + 1, $2, $3, $4, $5
+        FROM question_revisions
+        WHERE question_id = $1",
+    )
+    .bind(question.id.0)
+    .bind(&question.title)
+    .bind(&question.content)
+    .bind(&question.tags)
+    .bind(editor_id.0)
+    .execute(&mut *tx)
+    .await
+    .map(|_| ())
+}
+
+/// Record the current state of `answer` as its newest revision
+async fn add_answer_revision(
+    tx: &mut Transaction<'_, Postgres>,
+    answer: &Answer,
+    editor_id: &AccountId,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO answer_revisions
+            (answer_id, revision, content, editor_id)
+        SELECT $1, coalesce(max(revision), 0) + 1, $2, $3
+        FROM answer_revisions
+        WHERE answer_id = $1",
+    )
+    .bind(answer.id.0)
+    .bind(&answer.content)
+    .bind(editor_id.0)
+    .execute(&mut *tx)
+    .await
+    .map(|_| ())
+}
+
+/// Map a row of `questions` to a `Question`
+fn question_from_row(row: &PgRow) -> Question {
+    Question {
+        id: QuestionId(row.get("id")),
+        title: row.get("title"),
+        content: row.get("content"),
+        tags: row.get("tags"),
+        score: row.get("score"),
+        accepted_answer_id: row
+            .get::<Option<i32>, _>("accepted_answer_id")
+            .map(AnswerId),
+        deleted_at: row.get("deleted_at"),
+        version: row.get("version"),
+    }
+}
+
+/// Invalidate every access and refresh token issued to an account so far.
+/// The cutoff is compared with the `nbf` of access tokens, so it is taken
+/// from the clock which sets `nbf` instead of the one of the database
+async fn revoke_account_tokens(
+    tx: &mut Transaction<'_, Postgres>,
+    account_id: &AccountId,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE accounts SET tokens_valid_after = $1
+        WHERE id = $2",
+    )
+    .bind(Utc::now())
+    .bind(account_id.0)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW()
+        WHERE account_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(account_id.0)
+    .execute(&mut *tx)
+    .await
+    .map(|_| ())
+}
+
+/// Map a row of `tags` with its synonyms and usage count to a `Tag`
+fn tag_from_row(row: &PgRow) -> Tag {
+    Tag {
+        name: row.get("name"),
+        description: row.get("description"),
+        synonyms: row.get("synonyms"),
+        usage_count: row.get("usage_count"),
+    }
+}
+
+/// Read the `role` column, the check constraint only allows known roles
+fn role_from_row(row: &PgRow) -> Role {
+    row.get::<String, _>("role").parse().unwrap_or_default()
+}
+
+/// Map a row of `accounts` to an `Account`
+fn account_from_row(row: &PgRow) -> Account {
+    Account {
+        id: AccountId(row.get("id")),
+        email: row.get("email"),
+        password: row.get("password"),
+        role: role_from_row(row),
+        email_verified: row.get("email_verified"),
+        display_name: row.get("display_name"),
+    }
+}
+
+/// Map a row of `accounts` to an `AccountSummary`
+fn account_summary_from_row(row: &PgRow) -> AccountSummary {
+    AccountSummary {
+        id: AccountId(row.get("id")),
+        email: row.get("email"),
+        role: role_from_row(row),
+    }
+}
+
+/// Map a row of `answers` to an `Answer`
+fn answer_from_row(row: &PgRow) -> Answer {
+    Answer {
+        id: AnswerId(row.get("id")),
+        content: row.get("content"),
+        question_id: QuestionId(row.get("corresponding_question")),
+        score: row.get("score"),
+    }
+}
+
+/// Map a row of `questions_query` to the `Question` and its `Cursor`
+fn question_with_cursor(row: PgRow) -> (Question, Cursor) {
+    (
+        question_from_row(&row),
+        Cursor {
+            created_on: row.get("created_on"),
+            id: row.get("id"),
+        },
+    )
+}
+
+/// Queries for voting on one kind of post
+struct VoteQueries {
+    /// Lock the post, so concurrent votes recompute the score in turn
+    lock: &'static str,
+    upsert: &'static str,
+    delete: &'static str,
+    /// Recompute the score of the post from its votes
+    score: &'static str,
+}
+
+/// The id of the voted post and the queries for its kind
+fn vote_queries(target: &VoteTarget) -> (i32, VoteQueries) {
+    match target {
+        VoteTarget::Question(question_id) => (
+            question_id.0,
+            VoteQueries {
+                lock: "SELECT id FROM questions WHERE id = $1 FOR UPDATE",
+                upsert:
+                    "INSERT INTO votes (account_id, question_id, value)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (account_id, question_id)
+                    DO UPDATE SET value = EXCLUDED.value",
+                delete: "DELETE FROM votes
+                    WHERE account_id = $1 AND question_id = $2",
+                score: "UPDATE questions
+                    SET score = (SELECT coalesce(sum(value), 0)
+                        FROM votes WHERE question_id = $1)
+                    WHERE id = $1
+                    RETURNING score",
+            },
+        ),
+        VoteTarget::Answer(answer_id) => (
+            answer_id.0,
+            VoteQueries {
+                lock: "SELECT id FROM answers WHERE id = $1 FOR UPDATE",
+                upsert: "INSERT INTO votes (account_id, answer_id, value)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (account_id, answer_id)
+                    DO UPDATE SET value = EXCLUDED.value",
+                delete: "DELETE FROM votes
+                    WHERE account_id = $1 AND answer_id = $2",
+                score: "UPDATE answers
+                    SET score = (SELECT coalesce(sum(value), 0)
+                        FROM votes WHERE answer_id = $1)
+                    WHERE id = $1
+                    RETURNING score",
+            },
+        ),
+    }
+}
+
 /// Postgres Database connection pool for storing Questions and Answers
 #[derive(Clone, Debug)]
 pub struct Store {
@@ -29,25 +411,96 @@ impl Store {
             connection: db_pool,
         })
     }
-    ///
+    /// Fetch a page of questions matching `filter`. For cursor based
+    /// pagination the cursor of the next page is returned as well
     pub async fn get_questions(
         &self,
-        limit: Option<i32>,
-        offset: i32,
-    ) -> Result<Vec<Question>, handle_errors::Error> {
-        match sqlx::query("SELECT * from questions LIMIT $1 OFFSET $2")
-            .bind(limit)
-            .bind(offset)
-            .map(|row: PgRow| Question {
-                id: QuestionId(row.get("id")),
-                title: row.get("title"),
-                content: row.get("content"),
-                tags: row.get("tags"),
-            })
+        filter: QuestionFilter,
+        pagination: Pagination,
+    ) -> Result<(Vec<Question>, Option<Cursor>), handle_errors::Error>
+    {
+        let mut query = questions_query(&filter, &pagination);
+
+        let mut rows = match query
+            .build()
+            .map(question_with_cursor)
             .fetch_all(&self.connection)
             .await
         {
-            Ok(questions) => Ok(questions),
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                return Err(Error::DatabaseQueryError(e));
+            }
+        };
+
+        let next_cursor = match pagination {
+            Pagination::Cursor { limit, .. }
+                if rows.len() > limit.max(0) as usize =>
+            {
+                rows.truncate(limit.max(0) as usize);
+                rows.last().map(|(_, cursor)| cursor.clone())
+            }
+            _ => None,
+        };
+
+        Ok((
+            rows.into_iter().map(|(question, _)| question).collect(),
+            next_cursor,
+        ))
+    }
+
+    /// Fetch a page of questions matching `filter` together with the
+    /// total number of matching questions. Both are read from the same
+    /// snapshot, so they are consistent with each other
+    pub async fn get_questions_page(
+        &self,
+        filter: QuestionFilter,
+        limit: Option<i32>,
+        offset: i32,
+    ) -> Result<Page<Question>, handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let mut count_query = QueryBuilder::<Postgres>::new(
+            "SELECT count(*) FROM questions q WHERE true",
+        );
+        push_question_conditions(&mut count_query, &filter);
+        let mut page_query = questions_query(
+            &filter,
+            &Pagination::Offset { limit, offset },
+        );
+
+        let page = async {
+            sqlx::query(
+                "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY",
+            )
+            .execute(&mut tx)
+            .await?;
+            let total: i64 = count_query
+                .build()
+                .map(|row: PgRow| row.get(0))
+                .fetch_one(&mut tx)
+                .await?;
+            let items = page_query
+                .build()
+                .map(|row: PgRow| question_with_cursor(row).0)
+                .fetch_all(&mut tx)
+                .await?;
+            tx.commit().await?;
+
+            Ok(Page {
+                items,
+                total,
+                limit,
+                offset,
+            })
+        };
+
+        match page.await {
+            Ok(page) => Ok(page),
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(Error::DatabaseQueryError(e))
@@ -55,31 +508,58 @@ impl Store {
         }
     }
 
-    ///
-    pub async fn add_question(
+    /// Rank questions by relevance for a full-text `query`, taking the
+    /// title, the content and the answers of every question into account
+    pub async fn search_questions(
         &self,
-        new_question: NewQuestion,
-        account_id: AccountId,
-    ) -> Result<Question, handle_errors::Error> {
+        query: &str,
+        limit: Option<i32>,
+        offset: i32,
+    ) -> Result<Vec<SearchResult>, handle_errors::Error> {
         match sqlx::query(
-            "INSERT INTO questions (title, content, tags, account_id)
-                 VALUES ($1, $2, $3, $4)
-                 RETURNING id, title, content, tags, account_id",
+            "SELECT q.id, q.title, q.content, q.tags, q.score,
+                q.accepted_answer_id, q.deleted_at, q.version,
+                (ts_rank(q.search_vector, query)
+                    + coalesce(best_answer.rank, 0))::real AS rank,
+                ts_headline('english', html_escape(q.title), query, $2)
+                    AS title_snippet,
+                ts_headline('english', html_escape(q.content), query, $2)
+                    AS content_snippet,
+                best_answer.snippet AS answer_snippet
+            FROM questions q
+            CROSS JOIN websearch_to_tsquery('english', $1) AS query
+            LEFT JOIN LATERAL (
+                SELECT ts_rank(a.search_vector, query) AS rank,
+                    ts_headline('english', html_escape(a.content), query, $2)
+                        AS snippet
+                FROM answers a
+                WHERE a.corresponding_question = q.id
+                    AND a.search_vector @@ query
+                ORDER BY rank DESC
+                LIMIT 1
+            ) best_answer ON true
+            WHERE (q.search_vector @@ query OR best_answer.rank IS NOT NULL)
+                AND q.deleted_at IS NULL
+            ORDER BY rank DESC, q.id
+            LIMIT $3 OFFSET $4",
         )
-        .bind(new_question.title)
-        .bind(new_question.content)
-        .bind(new_question.tags)
-        .bind(account_id.0)
-        .map(|row: PgRow| Question {
-            id: QuestionId(row.get("id")),
-            title: row.get("title"),
-            content: row.get("content"),
-            tags: row.get("tags"),
+        .bind(query)
+        .bind(HEADLINE_OPTIONS)
+        .bind(limit)
+        .bind(offset)
+        .map(|row: PgRow| SearchResult {
+            question: question_from_row(&row),
+            rank: row.get("rank"),
+            snippets: Snippets {
+                title: row.get("title_snippet"),
+                content: row.get("content_snippet"),
+                answer: row.get("answer_snippet"),
+            },
         })
-        .fetch_one(&self.connection)
+        .fetch_all(&self.connection)
         .await
         {
-            Ok(question) => Ok(question),
+            Ok(results) => Ok(results),
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(Error::DatabaseQueryError(e))
@@ -87,34 +567,43 @@ impl Store {
         }
     }
 
-    ///
-    pub async fn update_question(
+    /// Fetch the known tags starting with `prefix`, most used first
+    pub async fn get_tags(
         &self,
-        question: Question,
-        question_id: i32,
-        account_id: AccountId,
-    ) -> Result<Question, handle_errors::Error> {
+        prefix: &str,
+        limit: Option<i32>,
+        offset: i32,
+    ) -> Result<Vec<Tag>, handle_errors::Error> {
+        let pattern = format!(
+            "{}%",
+            prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+
         match sqlx::query(
-            "UPDATE questions
-            SET title = $1, content = $2, tags = $3
-            WHERE id = $4 and account_id = $5
-            RETURNING id, title, content, tags",
+            "SELECT t.name, t.description,
+                ARRAY(SELECT s.synonym FROM tag_synonyms s
+                    WHERE s.tag_name = t.name ORDER BY s.synonym)
+                    AS synonyms,
+                count(q.id) AS usage_count
+            FROM tags t
+            LEFT JOIN questions q
+                ON q.tags @> ARRAY[t.name] AND q.deleted_at IS NULL
+            WHERE t.name LIKE $1
+            GROUP BY t.name
+            ORDER BY usage_count DESC, t.name
+            LIMIT $2 OFFSET $3",
         )
-        .bind(question.title)
-        .bind(question.content)
-        .bind(question.tags)
-        .bind(question_id)
-        .bind(account_id.0)
-        .map(|row: PgRow| Question {
-            id: QuestionId(row.get("id")),
-            title: row.get("title"),
-            content: row.get("content"),
-            tags: row.get("tags"),
-        })
-        .fetch_one(&self.connection)
+        .bind(pattern)
+        .bind(limit)
+        .bind(offset)
+        .map(|row: PgRow| tag_from_row(&row))
+        .fetch_all(&self.connection)
         .await
         {
-            Ok(question) => Ok(question),
+            Ok(tags) => Ok(tags),
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(Error::DatabaseQueryError(e))
@@ -122,17 +611,29 @@ impl Store {
         }
     }
 
-    ///
-    pub async fn delete_question(
+    /// Fetch a single tag with its synonyms and usage count
+    pub async fn get_tag(
         &self,
-        question_id: i32,
-    ) -> Result<bool, handle_errors::Error> {
-        match sqlx::query("DELETE FROM questions WHERE id = $1")
-            .bind(question_id)
-            .execute(&self.connection)
-            .await
+        name: &str,
+    ) -> Result<Tag, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT t.name, t.description,
+                ARRAY(SELECT s.synonym FROM tag_synonyms s
+                    WHERE s.tag_name = t.name ORDER BY s.synonym)
+                    AS synonyms,
+                (SELECT count(*) FROM questions q
+                    WHERE q.tags @> ARRAY[t.name] AND q.deleted_at IS NULL)
+                    AS usage_count
+            FROM tags t
+            WHERE t.name = $1",
+        )
+        .bind(name)
+        .map(|row: PgRow| tag_from_row(&row))
+        .fetch_optional(&self.connection)
+        .await
         {
-            Ok(_) => Ok(true),
+            Ok(Some(tag)) => Ok(tag),
+            Ok(None) => Err(Error::TagNotFound),
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(Error::DatabaseQueryError(e))
@@ -140,27 +641,22 @@ impl Store {
         }
     }
 
-    ///
-    pub async fn add_answer(
+    /// Set or, with `None`, remove the description of a tag
+    pub async fn set_tag_description(
         &self,
-        new_answer: NewAnswer,
-    ) -> Result<Answer, handle_errors::Error> {
+        name: &str,
+        description: Option<String>,
+    ) -> Result<(), handle_errors::Error> {
         match sqlx::query(
-            "INSERT INTO answers (content, corresponding_question) 
-                VALUES ($1, $2) 
-                RETURNING id, content, corresponding_question",
+            "UPDATE tags SET description = $1 WHERE name = $2",
         )
-        .bind(new_answer.content)
-        .bind(new_answer.question_id.0)
-        .map(|row: PgRow| Answer {
-            id: AnswerId(row.get("id")),
-            content: row.get("content"),
-            question_id: QuestionId(row.get("corresponding_question")),
-        })
-        .fetch_one(&self.connection)
+        .bind(description)
+        .bind(name)
+        .execute(&self.connection)
         .await
         {
-            Ok(answer) => Ok(answer),
+            Ok(res) if res.rows_affected() > 0 => Ok(()),
+            Ok(_) => Err(Error::TagNotFound),
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(Error::DatabaseQueryError(e))
@@ -168,79 +664,1896 @@ impl Store {
         }
     }
 
-    pub async fn add_account(
+    /// The tag `synonym` is an alternative spelling of, if any
+    pub async fn get_synonym_tag(
         &self,
-        account: Account,
-    ) -> Result<bool, handle_errors::Error> {
+        synonym: &str,
+    ) -> Result<Option<String>, handle_errors::Error> {
         match sqlx::query(
-            "INSERT INTO accounts (email, password)
-        VALUES ($1, $2)",
+            "SELECT tag_name FROM tag_synonyms WHERE synonym = $1",
         )
-        .bind(account.email)
-        .bind(account.password)
-        .execute(&self.connection)
+        .bind(synonym)
+        .map(|row: PgRow| row.get("tag_name"))
+        .fetch_optional(&self.connection)
         .await
         {
-            Ok(_) => Ok(true),
+            Ok(tag) => Ok(tag),
             Err(e) => {
-                tracing::event!(
-                    tracing::Level::ERROR,
-                    code = e
-                        .as_database_error()
-                        .unwrap()
-                        .code()
-                        .unwrap()
-                        .parse::<i32>()
-                        .unwrap(),
-                    db_message = e.as_database_error().unwrap().message(),
-                    constraint = e
-                        .as_database_error()
-                        .unwrap()
-                        .constraint()
-                        .unwrap()
-                );
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Merge `synonym` into `tag`: questions carrying `synonym` carry `tag`
+    /// instead, synonyms of `synonym` move over and `synonym` is replaced
+    /// by `tag` on every later write
+    pub async fn add_tag_synonym(
+        &self,
+        tag: &str,
+        synonym: &str,
+    ) -> Result<(), handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let merged = async {
+            sqlx::query(
+                "UPDATE tag_synonyms SET tag_name = $1 WHERE tag_name = $2",
+            )
+            .bind(tag)
+            .bind(synonym)
+            .execute(&mut tx)
+            .await?;
+            sqlx::query(
+                "UPDATE questions SET tags = ARRAY(
+                    SELECT renamed.name FROM (
+                        SELECT CASE WHEN t.name = $2 THEN $1 ELSE t.name END
+                            AS name,
+                            t.position
+                        FROM unnest(questions.tags)
+                            WITH ORDINALITY AS t(name, position)
+                    ) renamed
+                    GROUP BY renamed.name
+                    ORDER BY min(renamed.position)
+                )
+                WHERE tags @> ARRAY[$2::text]",
+            )
+            .bind(tag)
+            .bind(synonym)
+            .execute(&mut tx)
+            .await?;
+            sqlx::query("DELETE FROM tags WHERE name = $1")
+                .bind(synonym)
+                .execute(&mut tx)
+                .await?;
+            sqlx::query(
+                "INSERT INTO tag_synonyms (synonym, tag_name)
+                VALUES ($1, $2)",
+            )
+            .bind(synonym)
+            .bind(tag)
+            .execute(&mut tx)
+            .await?;
+            tx.commit().await
+        };
 
+        match merged.await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(Error::DatabaseQueryError(e))
             }
         }
     }
 
-    pub async fn get_account(
+    /// Fetch a single question together with all of its answers
+    pub async fn get_question(
         &self,
-        email: String,
-    ) -> Result<Account, handle_errors::Error> {
-        match sqlx::query("SELECT * from accounts where email = $1")
-            .bind(email)
-            .map(|row: PgRow| Account {
-                id: Some(AccountId(row.get("id"))),
-                email: row.get("email"),
-                password: row.get("password"),
-            })
-            .fetch_one(&self.connection)
-            .await
+        question_id: i32,
+    ) -> Result<QuestionWithAnswers, handle_errors::Error> {
+        let question = match sqlx::query(
+            "SELECT id, title, content, tags, score, accepted_answer_id,
+                deleted_at, version
+                FROM questions
+                WHERE id = $1",
+        )
+        .bind(question_id)
+        .map(|row: PgRow| question_from_row(&row))
+        .fetch_optional(&self.connection)
+        .await
         {
-            Ok(account) => Ok(account),
-            Err(error) => {
-                tracing::event!(tracing::Level::ERROR, "{:?}", error);
-                Err(Error::DatabaseQueryError(error))
+            Ok(Some(question)) if question.deleted_at.is_some() => {
+                return Err(Error::QuestionGone)
+            }
+            Ok(Some(question)) => question,
+            Ok(None) => return Err(Error::QuestionNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                return Err(Error::DatabaseQueryError(e));
+            }
+        };
+
+        let answers = self.get_answers(question_id).await?;
+
+        Ok(QuestionWithAnswers { question, answers })
+    }
+
+    /// Insert a new question owned by `account_id`, recorded as its first
+    /// revision
+    pub async fn add_question(
+        &self,
+        new_question: NewQuestion,
+        account_id: AccountId,
+    ) -> Result<Question, handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let question = async {
+            let tags = resolve_tags(&mut tx, new_question.tags).await?;
+            let question = sqlx::query(
+                "INSERT INTO questions (title, content, tags, account_id)
+                 VALUES ($1, $2, $3, $4)
+                 RETURNING id, title, content, tags, score,
+                    accepted_answer_id, deleted_at, version, account_id",
+            )
+            .bind(new_question.title)
+            .bind(new_question.content)
+            .bind(tags)
+            .bind(account_id.0)
+            .map(|row: PgRow| question_from_row(&row))
+            .fetch_one(&mut tx)
+            .await?;
+            add_question_revision(&mut tx, &question, &account_id).await?;
+            tx.commit().await?;
+            Ok(question)
+        };
+
+        match question.await {
+            Ok(question) => Ok(question),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
             }
         }
     }
 
-    pub(crate) async fn is_question_owner(
+    /// Overwrite title, content and tags of an existing question, the new
+    /// state is recorded as a revision. With `expected_version` the update
+    /// fails with `PreconditionFailed` if the question was changed since
+    pub async fn update_question(
         &self,
+        question: UpdatedQuestion,
         question_id: i32,
-        account_id: &AccountId,
-    ) -> Result<bool, handle_errors::Error> {
+        account_id: AccountId,
+        expected_version: Option<i32>,
+    ) -> Result<Question, handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let question = async {
+            let question = match update_question_row(
+                &mut tx,
+                question.title,
+                question.content,
+                question.tags,
+                question_id,
+                expected_version,
+            )
+            .await?
+            {
+                Some(question) => question,
+                None => return Ok(None),
+            };
+            add_question_revision(&mut tx, &question, &account_id).await?;
+            tx.commit().await?;
+            Ok(Some(question))
+        };
+
+        match question.await {
+            Ok(Some(question)) => Ok(question),
+            Ok(None) if expected_version.is_some() => {
+                Err(Error::PreconditionFailed)
+            }
+            Ok(None) => Err(Error::QuestionNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Overwrite only the fields of an existing question which are set in
+    /// `patch`, the new state is recorded as a revision. With
+    /// `expected_version` the update fails with `PreconditionFailed` if
+    /// the question was changed since
+    pub async fn patch_question(
+        &self,
+        patch: QuestionPatch,
+        question_id: i32,
+        account_id: AccountId,
+        expected_version: Option<i32>,
+    ) -> Result<Question, handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let question = async {
+            let tags = resolve_tags(&mut tx, patch.tags).await?;
+            let question = match sqlx::query(
+                "UPDATE questions
+                SET title = coalesce($1, title),
+                    content = coalesce($2, content),
+                    tags = coalesce($3, tags),
+                    version = version + 1
+                WHERE id = $4 and deleted_at IS NULL
+                    and ($5::int IS NULL OR version = $5)
+                RETURNING id, title, content, tags, score,
+                    accepted_answer_id, deleted_at, version",
+            )
+            .bind(patch.title)
+            .bind(patch.content)
+            .bind(tags)
+            .bind(question_id)
+            .bind(expected_version)
+            .map(|row: PgRow| question_from_row(&row))
+            .fetch_optional(&mut tx)
+            .await?
+            {
+                Some(question) => question,
+                None => return Ok(None),
+            };
+            add_question_revision(&mut tx, &question, &account_id).await?;
+            tx.commit().await?;
+            Ok(Some(question))
+        };
+
+        match question.await {
+            Ok(Some(question)) => Ok(question),
+            Ok(None) if expected_version.is_some() => {
+                Err(Error::PreconditionFailed)
+            }
+            Ok(None) => Err(Error::QuestionNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Fetch every revision of a question, oldest first
+    pub async fn get_question_revisions(
+        &self,
+        question_id: i32,
+    ) -> Result<Vec<QuestionRevision>, handle_errors::Error> {
         match sqlx::query(
-            "SELECT * from questions where id = $1 and account_id = $2",
+            "SELECT question_id, revision, title, content, tags, editor_id,
+                created_on
+            FROM question_revisions
+            WHERE question_id = $1
+            ORDER BY revision",
         )
         .bind(question_id)
-        .bind(account_id.0)
-        .fetch_optional(&self.connection)
+        .map(|row: PgRow| QuestionRevision {
+            question_id: QuestionId(row.get("question_id")),
+            revision: row.get("revision"),
+            title: row.get("title"),
+            content: row.get("content"),
+            tags: row.get("tags"),
+            editor_id: AccountId(row.get("editor_id")),
+            created_on: row.get("created_on"),
+        })
+        .fetch_all(&self.connection)
         .await
         {
-            Ok(question) => Ok(question.is_some()),
+            Ok(revisions) => Ok(revisions),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Roll a question back to an earlier revision. The restored state is
+    /// recorded as a new revision edited by `account_id`
+    pub async fn restore_question_revision(
+        &self,
+        question_id: i32,
+        revision: i32,
+        account_id: AccountId,
+    ) -> Result<Question, handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let question = async {
+            let restored = sqlx::query(
+                "SELECT title, content, tags FROM question_revisions
+                WHERE question_id = $1 AND revision = $2",
+            )
+            .bind(question_id)
+            .bind(revision)
+            .map(|row: PgRow| {
+                (row.get("title"), row.get("content"), row.get("tags"))
+            })
+            .fetch_optional(&mut tx)
+            .await?;
+            let (title, content, tags) = match restored {
+                Some(restored) => restored,
+                None => return Ok(None),
+            };
+
+            let question = match update_question_row(
+                &mut tx,
+                title,
+                content,
+                tags,
+                question_id,
+                None,
+            )
+            .await?
+            {
+                Some(question) => question,
+                None => return Ok(None),
+            };
+            add_question_revision(&mut tx, &question, &account_id).await?;
+            tx.commit().await?;
+            Ok(Some(question))
+        };
+
+        match question.await {
+            Ok(Some(question)) => Ok(question),
+            Ok(None) => Err(Error::RevisionNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Mark `answer_id` as the accepted answer of a question owned by
+    /// `account_id`, `None` removes the accepted answer
+    pub async fn accept_answer(
+        &self,
+        question_id: i32,
+        answer_id: Option<i32>,
+        account_id: AccountId,
+    ) -> Result<Question, handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE questions
+            SET accepted_answer_id = $1
+            WHERE id = $2 and account_id = $3
+            RETURNING id, title, content, tags, score, accepted_answer_id,
+            deleted_at, version",
+        )
+        .bind(answer_id)
+        .bind(question_id)
+        .bind(account_id.0)
+        .map(|row: PgRow| question_from_row(&row))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(question) => Ok(question),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Mark a question as deleted. Deleted questions and their answers are
+    /// kept, but hidden
+    pub async fn delete_question(
+        &self,
+        question_id: i32,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE questions
+            SET deleted_at = NOW()
+            WHERE id = $1 and deleted_at IS NULL",
+        )
+        .bind(question_id)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(res) => Ok(res.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Undo the deletion of a question
+    pub async fn restore_question(
+        &self,
+        question_id: i32,
+    ) -> Result<Question, handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE questions
+            SET deleted_at = NULL
+            WHERE id = $1 and deleted_at IS NOT NULL
+            RETURNING id, title, content, tags, score, accepted_answer_id,
+                deleted_at, version",
+        )
+        .bind(question_id)
+        .map(|row: PgRow| question_from_row(&row))
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(question)) => Ok(question),
+            Ok(None) => Err(Error::QuestionNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Fetch every answer given to a question, oldest first
+    pub async fn get_answers(
+        &self,
+        question_id: i32,
+    ) -> Result<Vec<Answer>, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT id, content, corresponding_question, score from answers
+                WHERE corresponding_question = $1
+                ORDER BY created_on, id",
+        )
+        .bind(question_id)
+        .map(|row: PgRow| answer_from_row(&row))
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(answers) => Ok(answers),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Fetch a single answer by its id, answers of deleted questions are
+    /// treated as missing
+    pub async fn get_answer(
+        &self,
+        answer_id: i32,
+    ) -> Result<Answer, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT an.id, an.content, an.corresponding_question, an.score
+                FROM answers an
+                JOIN questions q ON q.id = an.corresponding_question
+                WHERE an.id = $1 AND q.deleted_at IS NULL",
+        )
+        .bind(answer_id)
+        .map(|row: PgRow| answer_from_row(&row))
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(answer)) => Ok(answer),
+            Ok(None) => Err(Error::AnswerNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Insert a new answer to an existing question, owned by `account_id`
+    /// and recorded as its first revision
+    pub async fn add_answer(
+        &self,
+        new_answer: NewAnswer,
+        account_id: AccountId,
+    ) -> Result<Answer, handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let answer = async {
+            let answer = sqlx::query(
+                "INSERT INTO answers (content, corresponding_question, account_id)
+                VALUES ($1, $2, $3)
+                RETURNING id, content, corresponding_question, score",
+            )
+            .bind(new_answer.content)
+            .bind(new_answer.question_id.0)
+            .bind(account_id.0)
+            .map(|row: PgRow| answer_from_row(&row))
+            .fetch_one(&mut tx)
+            .await?;
+            add_answer_revision(&mut tx, &answer, &account_id).await?;
+            tx.commit().await?;
+            Ok(answer)
+        };
+
+        match answer.await {
+            Ok(answer) => Ok(answer),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Overwrite the content of an existing answer, the new state is
+    /// recorded as a revision
+    pub async fn update_answer(
+        &self,
+        answer: UpdatedAnswer,
+        answer_id: i32,
+        account_id: AccountId,
+    ) -> Result<Answer, handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let answer = async {
+            let answer = sqlx::query(
+                "UPDATE answers
+                SET content = $1
+                WHERE id = $2
+                RETURNING id, content, corresponding_question, score",
+            )
+            .bind(answer.content)
+            .bind(answer_id)
+            .map(|row: PgRow| answer_from_row(&row))
+            .fetch_one(&mut tx)
+            .await?;
+            add_answer_revision(&mut tx, &answer, &account_id).await?;
+            tx.commit().await?;
+            Ok(answer)
+        };
+
+        match answer.await {
+            Ok(answer) => Ok(answer),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Fetch every revision of an answer, oldest first
+    pub async fn get_answer_revisions(
+        &self,
+        answer_id: i32,
+    ) -> Result<Vec<AnswerRevision>, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT answer_id, revision, content, editor_id, created_on
+            FROM answer_revisions
+            WHERE answer_id = $1
+            ORDER BY revision",
+        )
+        .bind(answer_id)
+        .map(|row: PgRow| AnswerRevision {
+            answer_id: AnswerId(row.get("answer_id")),
+            revision: row.get("revision"),
+            content: row.get("content"),
+            editor_id: AccountId(row.get("editor_id")),
+            created_on: row.get("created_on"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(revisions) => Ok(revisions),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Remove an answer by its id
+    pub async fn delete_answer(
+        &self,
+        answer_id: i32,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query("DELETE FROM answers WHERE id = $1")
+            .bind(answer_id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(res) => Ok(res.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Fetch every comment on a question or an answer, oldest first
+    pub async fn get_comments(
+        &self,
+        target: &CommentTarget,
+    ) -> Result<Vec<Comment>, handle_errors::Error> {
+        let (query, id) = match target {
+            CommentTarget::Question(question_id) => (
+                "SELECT id, content, question_id, answer_id from comments
+                    WHERE question_id = $1
+                    ORDER BY created_on, id",
+                question_id.0,
+            ),
+            CommentTarget::Answer(answer_id) => (
+                "SELECT id, content, question_id, answer_id from comments
+                    WHERE answer_id = $1
+                    ORDER BY created_on, id",
+                answer_id.0,
+            ),
+        };
+
+        match sqlx::query(query)
+            .bind(id)
+            .map(|row: PgRow| Comment {
+                id: CommentId(row.get("id")),
+                content: row.get("content"),
+                question_id: row
+                    .get::<Option<i32>, _>("question_id")
+                    .map(QuestionId),
+                answer_id: row
+                    .get::<Option<i32>, _>("answer_id")
+                    .map(AnswerId),
+            })
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(comments) => Ok(comments),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Fetch a single comment by its id
+    pub async fn get_comment(
+        &self,
+        comment_id: i32,
+    ) -> Result<Comment, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT id, content, question_id, answer_id from comments
+                WHERE id = $1",
+        )
+        .bind(comment_id)
+        .map(|row: PgRow| Comment {
+            id: CommentId(row.get("id")),
+            content: row.get("content"),
+            question_id: row
+                .get::<Option<i32>, _>("question_id")
+                .map(QuestionId),
+            answer_id: row
+                .get::<Option<i32>, _>("answer_id")
+                .map(AnswerId),
+        })
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(comment)) => Ok(comment),
+            Ok(None) => Err(Error::CommentNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Insert a new comment on a question or an answer, owned by
+    /// `account_id`
+    pub async fn add_comment(
+        &self,
+        target: &CommentTarget,
+        new_comment: NewComment,
+        account_id: AccountId,
+    ) -> Result<Comment, handle_errors::Error> {
+        let (question_id, answer_id) = match target {
+            CommentTarget::Question(question_id) => {
+                (Some(question_id.0), None)
+            }
+            CommentTarget::Answer(answer_id) => (None, Some(answer_id.0)),
+        };
+
+        match sqlx::query(
+            "INSERT INTO comments (content, question_id, answer_id, account_id)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, content, question_id, answer_id",
+        )
+        .bind(new_comment.content)
+        .bind(question_id)
+        .bind(answer_id)
+        .bind(account_id.0)
+        .map(|row: PgRow| Comment {
+            id: CommentId(row.get("id")),
+            content: row.get("content"),
+            question_id: row
+                .get::<Option<i32>, _>("question_id")
+                .map(QuestionId),
+            answer_id: row.get::<Option<i32>, _>("answer_id").map(AnswerId),
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(comment) => Ok(comment),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Overwrite the content of an existing comment
+    pub async fn update_comment(
+        &self,
+        comment: NewComment,
+        comment_id: i32,
+        account_id: AccountId,
+    ) -> Result<Comment, handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE comments
+            SET content = $1
+            WHERE id = $2 and account_id = $3
+            RETURNING id, content, question_id, answer_id",
+        )
+        .bind(comment.content)
+        .bind(comment_id)
+        .bind(account_id.0)
+        .map(|row: PgRow| Comment {
+            id: CommentId(row.get("id")),
+            content: row.get("content"),
+            question_id: row
+                .get::<Option<i32>, _>("question_id")
+                .map(QuestionId),
+            answer_id: row
+                .get::<Option<i32>, _>("answer_id")
+                .map(AnswerId),
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(comment) => Ok(comment),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Remove a comment by its id
+    pub async fn delete_comment(
+        &self,
+        comment_id: i32,
+        account_id: AccountId,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "DELETE FROM comments WHERE id = $1 and account_id = $2",
+        )
+        .bind(comment_id)
+        .bind(account_id.0)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(res) => Ok(res.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Add or change the vote of `account_id` on a post and return the
+    /// new score of the post
+    pub async fn add_vote(
+        &self,
+        target: &VoteTarget,
+        account_id: AccountId,
+        direction: VoteDirection,
+    ) -> Result<Score, handle_errors::Error> {
+        let (id, queries) = vote_queries(target);
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let score = async {
+            sqlx::query(queries.lock).bind(id).execute(&mut tx).await?;
+            sqlx::query(queries.upsert)
+                .bind(account_id.0)
+                .bind(id)
+                .bind(direction.value())
+                .execute(&mut tx)
+                .await?;
+            let score = sqlx::query(queries.score)
+                .bind(id)
+                .map(|row: PgRow| Score {
+                    score: row.get("score"),
+                })
+                .fetch_one(&mut tx)
+                .await?;
+            tx.commit().await?;
+            Ok(score)
+        };
+
+        match score.await {
+            Ok(score) => Ok(score),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Remove the vote of `account_id` from a post and return the new
+    /// score of the post
+    pub async fn delete_vote(
+        &self,
+        target: &VoteTarget,
+        account_id: AccountId,
+    ) -> Result<Score, handle_errors::Error> {
+        let (id, queries) = vote_queries(target);
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let score = async {
+            sqlx::query(queries.lock).bind(id).execute(&mut tx).await?;
+            sqlx::query(queries.delete)
+                .bind(account_id.0)
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+            let score = sqlx::query(queries.score)
+                .bind(id)
+                .map(|row: PgRow| Score {
+                    score: row.get("score"),
+                })
+                .fetch_one(&mut tx)
+                .await?;
+            tx.commit().await?;
+            Ok(score)
+        };
+
+        match score.await {
+            Ok(score) => Ok(score),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Create an account with an already hashed password
+    pub async fn add_account(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> Result<Account, handle_errors::Error> {
+        match sqlx::query(
+            "INSERT INTO accounts (email, password)
+        VALUES ($1, $2)
+        RETURNING *",
+        )
+        .bind(email)
+        .bind(password)
+        .map(|row: PgRow| account_from_row(&row))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(account) => Ok(account),
+            Err(e) => {
+                tracing::event!(
+                    tracing::Level::ERROR,
+                    code = e
+                        .as_database_error()
+                        .unwrap()
+                        .code()
+                        .unwrap()
+                        .parse::<i32>()
+                        .unwrap(),
+                    db_message = e.as_database_error().unwrap().message(),
+                    constraint = e
+                        .as_database_error()
+                        .unwrap()
+                        .constraint()
+                        .unwrap()
+                );
+
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Fetch every account for admins, ordered by id
+    pub async fn get_accounts(
+        &self,
+    ) -> Result<Vec<AccountSummary>, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT id, email, role from accounts
+            WHERE deleted_at IS NULL ORDER BY id",
+        )
+        .map(|row: PgRow| account_summary_from_row(&row))
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(accounts) => Ok(accounts),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    /// Change the role of an account
+    pub async fn update_role(
+        &self,
+        account_id: &AccountId,
+        role: Role,
+    ) -> Result<AccountSummary, handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE accounts SET role = $1
+            WHERE id = $2 AND deleted_at IS NULL
+            RETURNING id, email, role",
+        )
+        .bind(role.as_str())
+        .bind(account_id.0)
+        .map(|row: PgRow| account_summary_from_row(&row))
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(account)) => Ok(account),
+            Ok(None) => Err(Error::AccountNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    pub async fn get_account(
+        &self,
+        email: String,
+    ) -> Result<Account, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT * from accounts where email = $1 AND deleted_at IS NULL",
+        )
+            .bind(email)
+            .map(|row: PgRow| account_from_row(&row))
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(account) => Ok(account),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    pub async fn get_account_by_id(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<Account, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT * from accounts where id = $1 AND deleted_at IS NULL",
+        )
+        .bind(account_id.0)
+        .map(|row: PgRow| account_from_row(&row))
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(account)) => Ok(account),
+            Ok(None) => Err(Error::AccountNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    /// Set a new password hash, revoke every token of the account and
+    /// every pending password reset
+    pub async fn change_password(
+        &self,
+        account_id: &AccountId,
+        password: &str,
+    ) -> Result<(), handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let change = async {
+            let res = sqlx::query(
+                "UPDATE accounts SET password = $1
+                WHERE id = $2 AND deleted_at IS NULL",
+            )
+            .bind(password)
+            .bind(account_id.0)
+            .execute(&mut tx)
+            .await?;
+            if res.rows_affected() == 0 {
+                return Ok(false);
+            }
+
+            sqlx::query(
+                "UPDATE password_resets SET used_at = NOW()
+                WHERE account_id = $1 AND used_at IS NULL",
+            )
+            .bind(account_id.0)
+            .execute(&mut tx)
+            .await?;
+            revoke_account_tokens(&mut tx, account_id).await?;
+            tx.commit().await?;
+            Ok(true)
+        };
+
+        match change.await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Error::AccountNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Set a new email address, which has to be verified again
+    pub async fn change_email(
+        &self,
+        account_id: &AccountId,
+        email: &str,
+    ) -> Result<(), handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE accounts SET email = $1, email_verified = false
+            WHERE id = $2 AND deleted_at IS NULL",
+        )
+        .bind(email)
+        .bind(account_id.0)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(res) if res.rows_affected() > 0 => Ok(()),
+            Ok(_) => Err(Error::AccountNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    /// Anonymize an account instead of deleting its row, so questions,
+    /// answers, comments and votes keep pointing to a valid account id.
+    /// The email address is freed and logging in becomes impossible
+    pub async fn delete_account(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<(), handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let delete = async {
+            let res = sqlx::query(
+                "UPDATE accounts SET
+                    email = 'deleted-' || id || '@deleted.invalid',
+                    password = '',
+                    email_verified = false,
+                    deleted_at = NOW()
+                WHERE id = $1 AND deleted_at IS NULL",
+            )
+            .bind(account_id.0)
+            .execute(&mut tx)
+            .await?;
+            if res.rows_affected() == 0 {
+                return Ok(false);
+            }
+
+            sqlx::query(
+                "DELETE FROM password_resets WHERE account_id = $1",
+            )
+            .bind(account_id.0)
+            .execute(&mut tx)
+            .await?;
+            revoke_account_tokens(&mut tx, account_id).await?;
+            tx.commit().await?;
+            Ok(true)
+        };
+
+        match delete.await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Error::AccountNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Set the display name shown on the public profile
+    pub async fn set_display_name(
+        &self,
+        account_id: &AccountId,
+        display_name: &str,
+    ) -> Result<(), handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE accounts SET display_name = $1
+            WHERE id = $2 AND deleted_at IS NULL",
+        )
+        .bind(display_name)
+        .bind(account_id.0)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(res) if res.rows_affected() > 0 => Ok(()),
+            Ok(_) => Err(Error::AccountNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    /// Fetch the public profile of an account with its activity counts and
+    /// reputation. Deleted accounts have no profile
+    pub async fn get_public_user(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<PublicUser, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT a.id, coalesce(a.display_name, 'user' || a.id)
+                    AS display_name,
+                a.created_on,
+                (SELECT count(*) FROM questions q
+                    WHERE q.account_id = a.id AND q.deleted_at IS NULL)
+                    AS question_count,
+                (SELECT coalesce(sum(q.score), 0) FROM questions q
+                    WHERE q.account_id = a.id AND q.deleted_at IS NULL)
+                    AS question_score,
+                (SELECT count(*) FROM answers an
+                    JOIN questions q ON q.id = an.corresponding_question
+                    WHERE an.account_id = a.id AND q.deleted_at IS NULL)
+                    AS answer_count,
+                (SELECT coalesce(sum(an.score), 0) FROM answers an
+                    JOIN questions q ON q.id = an.corresponding_question
+                    WHERE an.account_id = a.id AND q.deleted_at IS NULL)
+                    AS answer_score,
+                (SELECT count(*) FROM questions q
+                    JOIN answers an ON an.id = q.accepted_answer_id
+                    WHERE an.account_id = a.id AND q.account_id <> a.id
+                        AND q.deleted_at IS NULL)
+                    AS accepted_answers
+            FROM accounts a
+            WHERE a.id = $1 AND a.deleted_at IS NULL",
+        )
+        .bind(account_id.0)
+        .map(|row: PgRow| PublicUser {
+            id: AccountId(row.get("id")),
+            display_name: row.get("display_name"),
+            joined: row.get("created_on"),
+            question_count: row.get("question_count"),
+            answer_count: row.get("answer_count"),
+            reputation: reputation(
+                row.get("question_score"),
+                row.get("answer_score"),
+                row.get("accepted_answers"),
+            ),
+        })
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(Error::AccountNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    /// Whether an account exists and has not been deleted
+    pub async fn account_exists(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT EXISTS (
+                SELECT 1 FROM accounts WHERE id = $1 AND deleted_at IS NULL
+            ) AS found",
+        )
+        .bind(account_id.0)
+        .map(|row: PgRow| row.get("found"))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(found) => Ok(found),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    /// Fetch the questions asked by an account, newest first. Deleted
+    /// questions are left out
+    pub async fn get_user_questions(
+        &self,
+        account_id: &AccountId,
+        limit: Option<i32>,
+        offset: i32,
+    ) -> Result<Vec<Question>, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT id, title, content, tags, score, accepted_answer_id,
+                deleted_at, version
+            FROM questions
+            WHERE account_id = $1 AND deleted_at IS NULL
+            ORDER BY created_on DESC, id DESC
+            LIMIT $2 OFFSET $3",
+        )
+        .bind(account_id.0)
+        .bind(limit)
+        .bind(offset)
+        .map(|row: PgRow| question_from_row(&row))
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(questions) => Ok(questions),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Fetch the answers given by an account, newest first. Answers to
+    /// deleted questions are left out
+    pub async fn get_user_answers(
+        &self,
+        account_id: &AccountId,
+        limit: Option<i32>,
+        offset: i32,
+    ) -> Result<Vec<Answer>, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT an.id, an.content, an.corresponding_question, an.score
+            FROM answers an
+            JOIN questions q ON q.id = an.corresponding_question
+            WHERE an.account_id = $1 AND q.deleted_at IS NULL
+            ORDER BY an.created_on DESC, an.id DESC
+            LIMIT $2 OFFSET $3",
+        )
+        .bind(account_id.0)
+        .bind(limit)
+        .bind(offset)
+        .map(|row: PgRow| answer_from_row(&row))
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(answers) => Ok(answers),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Mark the email address of an account as verified
+    pub async fn verify_email(
+        &self,
+        email: &str,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE accounts SET email_verified = true WHERE email = $1",
+        )
+        .bind(email)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(res) => Ok(res.rows_affected() > 0),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    /// Store the hash of a refresh token belonging to the login session
+    /// `session_id`
+    pub async fn add_refresh_token(
+        &self,
+        token_hash: &str,
+        session_id: &str,
+        account_id: &AccountId,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), handle_errors::Error> {
+        match sqlx::query(
+            "INSERT INTO refresh_tokens
+                (token_hash, session_id, account_id, expires_at)
+            VALUES ($1, $2, $3, $4)",
+        )
+        .bind(token_hash)
+        .bind(session_id)
+        .bind(account_id.0)
+        .bind(expires_at)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Use up the refresh token hashed as `old_hash` and store `new_hash`
+    /// in its place. Returns the account and login session the token
+    /// belongs to. Reusing an already used refresh token revokes the
+    /// whole login session, as the token has probably been stolen
+    pub async fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(AccountId, Role, String), handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let rotated = async {
+            let token = sqlx::query(
+                "SELECT r.session_id, r.account_id, a.role,
+                    r.revoked_at IS NOT NULL AS revoked,
+                    r.expires_at < NOW() AS expired
+                FROM refresh_tokens r
+                JOIN accounts a ON a.id = r.account_id
+                WHERE r.token_hash = $1
+                FOR UPDATE OF r",
+            )
+            .bind(old_hash)
+            .map(|row: PgRow| {
+                (
+                    row.get::<String, _>("session_id"),
+                    AccountId(row.get("account_id")),
+                    role_from_row(&row),
+                    row.get::<bool, _>("revoked"),
+                    row.get::<bool, _>("expired"),
+                )
+            })
+            .fetch_optional(&mut tx)
+            .await?;
+            let (session_id, account_id, role) = match token {
+                Some((session_id, _, _, true, _)) => {
+                    sqlx::query(
+                        "UPDATE refresh_tokens SET revoked_at = NOW()
+                        WHERE session_id = $1 AND revoked_at IS NULL",
+                    )
+                    .bind(session_id)
+                    .execute(&mut tx)
+                    .await?;
+                    tx.commit().await?;
+                    return Ok(None);
+                }
+                Some((_, _, _, _, true)) | None => return Ok(None),
+                Some((session_id, account_id, role, _, _)) => {
+                    (session_id, account_id, role)
+                }
+            };
+
+            sqlx::query(
+                "UPDATE refresh_tokens SET revoked_at = NOW()
+                WHERE token_hash = $1",
+            )
+            .bind(old_hash)
+            .execute(&mut tx)
+            .await?;
+            sqlx::query(
+                "INSERT INTO refresh_tokens
+                    (token_hash, session_id, account_id, expires_at)
+                VALUES ($1, $2, $3, $4)",
+            )
+            .bind(new_hash)
+            .bind(&session_id)
+            .bind(account_id.0)
+            .bind(expires_at)
+            .execute(&mut tx)
+            .await?;
+            tx.commit().await?;
+            Ok(Some((account_id, role, session_id)))
+        };
+
+        match rotated.await {
+            Ok(Some(rotated)) => Ok(rotated),
+            Ok(None) => Err(Error::Unauthorized),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Revoke the access token of `session` and every refresh token of
+    /// its login session
+    pub async fn revoke_session(
+        &self,
+        session: &Session,
+    ) -> Result<(), handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let revoked = async {
+            sqlx::query(
+                "DELETE FROM revoked_tokens WHERE expires_at < NOW()",
+            )
+            .execute(&mut tx)
+            .await?;
+            if let Some(jti) = &session.jti {
+                sqlx::query(
+                    "INSERT INTO revoked_tokens (jti, expires_at)
+                    VALUES ($1, $2)
+                    ON CONFLICT DO NOTHING",
+                )
+                .bind(jti)
+                .bind(session.exp)
+                .execute(&mut tx)
+                .await?;
+            }
+            if let Some(sid) = &session.sid {
+                sqlx::query(
+                    "UPDATE refresh_tokens SET revoked_at = NOW()
+                    WHERE session_id = $1 AND revoked_at IS NULL",
+                )
+                .bind(sid)
+                .execute(&mut tx)
+                .await?;
+            }
+            tx.commit().await
+        };
+
+        match revoked.await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Revoke every token issued to an account so far
+    pub async fn revoke_all_sessions(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<(), handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let revoked = async {
+            revoke_account_tokens(&mut tx, account_id).await?;
+            tx.commit().await
+        };
+
+        match revoked.await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Store the hash of a password reset token for the account
+    /// registered with `email`. Returns `false` if there is no such account
+    pub async fn add_password_reset(
+        &self,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "INSERT INTO password_resets (token_hash, account_id, expires_at)
+            SELECT $1, id, $2 FROM accounts WHERE email = $3",
+        )
+        .bind(token_hash)
+        .bind(expires_at)
+        .bind(email)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(res) => Ok(res.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Whether the password reset token hashed as `token_hash` is unused
+    /// and not expired
+    pub async fn is_password_reset_valid(
+        &self,
+        token_hash: &str,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT 1 FROM password_resets
+            WHERE token_hash = $1
+                AND used_at IS NULL AND expires_at > NOW()",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Use up the password reset token hashed as `token_hash`, set the new
+    /// password and revoke every token of the account
+    pub async fn reset_password(
+        &self,
+        token_hash: &str,
+        password: &str,
+    ) -> Result<(), handle_errors::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })?;
+
+        let reset = async {
+            let account_id = match sqlx::query(
+                "UPDATE password_resets SET used_at = NOW()
+                WHERE token_hash = $1
+                    AND used_at IS NULL AND expires_at > NOW()
+                RETURNING account_id",
+            )
+            .bind(token_hash)
+            .map(|row: PgRow| AccountId(row.get("account_id")))
+            .fetch_optional(&mut tx)
+            .await?
+            {
+                Some(account_id) => account_id,
+                None => return Ok(false),
+            };
+
+            sqlx::query("UPDATE accounts SET password = $1 WHERE id = $2")
+                .bind(password)
+                .bind(account_id.0)
+                .execute(&mut tx)
+                .await?;
+            sqlx::query(
+                "UPDATE password_resets SET used_at = NOW()
+                WHERE account_id = $1 AND used_at IS NULL",
+            )
+            .bind(account_id.0)
+            .execute(&mut tx)
+            .await?;
+            revoke_account_tokens(&mut tx, &account_id).await?;
+            tx.commit().await?;
+            Ok(true)
+        };
+
+        match reset.await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Error::InvalidToken),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Point in time until which any of `keys` is locked out of logging
+    /// in, if one of them is
+    pub async fn get_login_lockout(
+        &self,
+        keys: &[String],
+    ) -> Result<Option<DateTime<Utc>>, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT max(locked_until) AS locked_until FROM login_attempts
+            WHERE key = ANY($1) AND locked_until > NOW()",
+        )
+        .bind(keys)
+        .map(|row: PgRow| row.get("locked_until"))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(locked_until) => Ok(locked_until),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Count a failed login for `key` and return the failures in a row.
+    /// Failures older than `reset_after` seconds are forgotten
+    pub async fn add_login_failure(
+        &self,
+        key: &str,
+        reset_after: u64,
+    ) -> Result<u32, handle_errors::Error> {
+        match sqlx::query(
+            "INSERT INTO login_attempts (key, failures, last_failure)
+            VALUES ($1, 1, NOW())
+            ON CONFLICT (key) DO UPDATE SET
+                failures = CASE
+                    WHEN login_attempts.last_failure
+                        < NOW() - make_interval(secs => $2) THEN 1
+                    ELSE login_attempts.failures + 1
+                END,
+                last_failure = NOW()
+            RETURNING failures",
+        )
+        .bind(key)
+        .bind(reset_after as f64)
+        .map(|row: PgRow| row.get::<i32, _>("failures"))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(failures) => Ok(failures as u32),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Lock `key` out of logging in for `seconds`
+    pub async fn lock_login(
+        &self,
+        key: &str,
+        seconds: u64,
+    ) -> Result<(), handle_errors::Error> {
+        match sqlx::query(
+            "UPDATE login_attempts
+            SET locked_until = NOW() + make_interval(secs => $2)
+            WHERE key = $1",
+        )
+        .bind(key)
+        .bind(seconds as f64)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Forget the failed logins of `key`
+    pub async fn clear_login_failures(
+        &self,
+        key: &str,
+    ) -> Result<(), handle_errors::Error> {
+        match sqlx::query("DELETE FROM login_attempts WHERE key = $1")
+            .bind(key)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Delete the login attempts whose failures are older than
+    /// `reset_after` seconds and whose lockout has passed, they behave like
+    /// missing ones. Returns the number of deleted attempts
+    pub async fn prune_login_attempts(
+        &self,
+        reset_after: u64,
+    ) -> Result<u64, handle_errors::Error> {
+        match sqlx::query(
+            "DELETE FROM login_attempts
+            WHERE last_failure < NOW() - make_interval(secs => $1)
+                AND (locked_until IS NULL OR locked_until <= NOW())",
+        )
+        .bind(reset_after as f64)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Whether the access token of `session` has been revoked, either on
+    /// its own or by logging out all sessions of the account
+    pub async fn is_token_revoked(
+        &self,
+        session: &Session,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT EXISTS (
+                SELECT 1 FROM revoked_tokens WHERE jti = $1
+            ) OR EXISTS (
+                SELECT 1 FROM accounts
+                WHERE id = $2 AND tokens_valid_after > $3
+            ) AS revoked",
+        )
+        .bind(&session.jti)
+        .bind(session.account_id.0)
+        .bind(session.nbf)
+        .map(|row: PgRow| row.get("revoked"))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(revoked) => Ok(revoked),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Take a token from the rate limit bucket of `key`, which holds up
+    /// to `capacity` tokens and refills `refill_per_second`. Returns
+    /// whether a token was available and the tokens left
+    pub async fn take_rate_limit_token(
+        &self,
+        key: &str,
+        capacity: f64,
+        refill_per_second: f64,
+    ) -> Result<(bool, f64), handle_errors::Error> {
+        match sqlx::query(
+            "WITH refilled AS (
+                SELECT least(
+                    $2,
+                    tokens
+                        + $3 * extract(epoch FROM NOW() - updated_at)::float8
+                ) AS tokens
+                FROM rate_limits
+                WHERE key = $1
+                FOR UPDATE
+            ), bucket AS (
+                SELECT coalesce((SELECT tokens FROM refilled), $2) AS tokens
+            ), taken AS (
+                SELECT tokens >= 1 AS allowed,
+                    CASE WHEN tokens >= 1 THEN tokens - 1 ELSE tokens END
+                        AS tokens
+                FROM bucket
+            )
+            INSERT INTO rate_limits
+                (key, tokens, allowed, updated_at, full_at)
+            SELECT $1, tokens, allowed, NOW(),
+                NOW() + make_interval(secs => ($2 - tokens) / $3)
+            FROM taken
+            ON CONFLICT (key) DO UPDATE SET
+                tokens = excluded.tokens,
+                allowed = excluded.allowed,
+                updated_at = excluded.updated_at,
+                full_at = excluded.full_at
+            RETURNING allowed, tokens",
+        )
+        .bind(key)
+        .bind(capacity)
+        .bind(refill_per_second)
+        .map(|row: PgRow| (row.get("allowed"), row.get("tokens")))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(taken) => Ok(taken),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Delete the rate limit buckets which are full again, they behave
+    /// like missing ones. Returns the number of deleted buckets
+    pub async fn prune_rate_limits(
+        &self,
+    ) -> Result<u64, handle_errors::Error> {
+        match sqlx::query("DELETE FROM rate_limits WHERE full_at <= NOW()")
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub(crate) async fn is_question_owner(
+        &self,
+        question_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT * from questions where id = $1 and account_id = $2",
+        )
+        .bind(question_id)
+        .bind(account_id.0)
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(question) => Ok(question.is_some()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    pub(crate) async fn question_exists(
+        &self,
+        question_id: i32,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT id from questions where id = $1 and deleted_at IS NULL",
+        )
+            .bind(question_id)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(question) => Ok(question.is_some()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    pub(crate) async fn is_answer_owner(
+        &self,
+        answer_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT * from answers where id = $1 and account_id = $2",
+        )
+        .bind(answer_id)
+        .bind(account_id.0)
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(answer) => Ok(answer.is_some()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    pub(crate) async fn is_comment_owner(
+        &self,
+        comment_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, handle_errors::Error> {
+        match sqlx::query(
+            "SELECT * from comments where id = $1 and account_id = $2",
+        )
+        .bind(comment_id)
+        .bind(account_id.0)
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(comment) => Ok(comment.is_some()),
             Err(error) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", error);
                 Err(Error::DatabaseQueryError(error))
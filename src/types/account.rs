@@ -1,26 +1,403 @@
+use std::str::FromStr;
+
+use handle_errors::{Error, FieldError};
 use serde::{Deserialize, Serialize};
 use chrono::prelude::*;
 
+/// Minimum number of characters of a password
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Maximum number of characters of a password, hashing is expensive
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+/// Maximum length of an email address, the size of the column
+pub const MAX_EMAIL_LENGTH: usize = 255;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub exp: DateTime<Utc>,
     pub account_id: AccountId,
     pub nbf: DateTime<Utc>,
+    /// Role of the account when the token was issued
+    #[serde(default)]
+    pub role: Role,
+    /// Unique id of the token, used to revoke it
+    #[serde(default)]
+    pub jti: Option<String>,
+    /// Id of the login session the token belongs to, shared with its
+    /// refresh tokens
+    #[serde(default)]
+    pub sid: Option<String>,
 }
 
+/// Tokens handed out on login and refresh
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPair {
+    /// Short-lived token for the `auth` header
+    pub access_token: String,
+    /// Single use token for `POST /token/refresh`
+    pub refresh_token: String,
+    /// Seconds until the `access_token` expires
+    pub expires_in: i64,
+}
 
+/// Payload for exchanging a refresh token for new tokens
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// An account as stored, including its password hash. It is neither
+/// deserialized from requests nor serialized into responses, those use
+/// `RegisterRequest`, `LoginRequest` and `AccountProfile`,
+/// `AccountSummary` or `PublicUser`
+#[derive(Debug, Clone)]
 pub struct Account {
-    pub id: Option<AccountId>,
+    pub id: AccountId,
     pub email: String,
+    /// Argon2 hash of the password
     pub password: String,
+    pub role: Role,
+    pub email_verified: bool,
+    pub display_name: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct AccountId(pub i32);
 
+/// Payload for registering a new account
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterRequest {
+    pub email: String,
+    pub password: String,
+}
+
+impl RegisterRequest {
+    /// Check the email format and the password strength
+    pub fn validate(&self) -> Result<(), Error> {
+        validate(vec![
+            ("email", check_email(&self.email)),
+            ("password", check_password(&self.password)),
+        ])
+    }
+}
+
+/// Payload for logging in
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+/// Payload for requesting a password reset link
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NewAccount {
+pub struct ForgotPassword {
+    pub email: String,
+}
+
+/// Payload for setting a new password with a reset token
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordReset {
+    pub token: String,
+    pub password: String,
+}
+
+/// An account as listed for admins, without its password
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountSummary {
+    pub id: AccountId,
+    pub email: String,
+    pub role: Role,
+}
+
+/// An account as returned to its owner, on registration and by `GET /me`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountProfile {
+    pub id: AccountId,
+    pub email: String,
+    /// `None` until a display name has been chosen
+    pub display_name: Option<String>,
+    pub role: Role,
+    pub email_verified: bool,
+}
+
+impl From<Account> for AccountProfile {
+    fn from(account: Account) -> Self {
+        AccountProfile {
+            id: account.id,
+            email: account.email,
+            display_name: account.display_name,
+            role: account.role,
+            email_verified: account.email_verified,
+        }
+    }
+}
+
+/// Payload for changing the password, the current one has to be given
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordChange {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Payload for changing the email address, confirmed with the password
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailChange {
     pub email: String,
     pub password: String,
 }
+
+/// Payload for changing the role of an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdatedRole {
+    pub role: Role,
+}
+
+/// Role of an account, ordered by the permissions it grants
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = handle_errors::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(handle_errors::Error::InvalidParameter(format!(
+                "role={}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Reason why `email` does not look like an email address: it needs a
+/// local part and a domain containing a dot, without whitespace
+/// # Example usage
+/// ```rust
+/// use rustwebdev::types::account::check_email;
+///
+/// assert_eq!(check_email("test@email.com"), None);
+/// assert!(check_email("test@localhost").is_some());
+/// assert!(check_email("test email@email.com").is_some());
+/// ```
+pub fn check_email(email: &str) -> Option<String> {
+    if email.len() > MAX_EMAIL_LENGTH {
+        return Some(format!(
+            "must be at most {} characters long",
+            MAX_EMAIL_LENGTH
+        ));
+    }
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.chars().any(char::is_whitespace)
+        }
+        None => false,
+    };
+    if valid {
+        None
+    } else {
+        Some("must be a valid email address".to_string())
+    }
+}
+
+/// Reason why `password` is too weak: it needs `MIN_PASSWORD_LENGTH` to
+/// `MAX_PASSWORD_LENGTH` characters and next to letters at least one
+/// digit or symbol
+/// # Example usage
+/// ```rust
+/// use rustwebdev::types::account::check_password;
+///
+/// assert_eq!(check_password("correct horse 9"), None);
+/// assert!(check_password("short1").is_some());
+/// assert!(check_password("onlyletters").is_some());
+/// ```
+pub fn check_password(password: &str) -> Option<String> {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        Some(format!(
+            "must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        ))
+    } else if length > MAX_PASSWORD_LENGTH {
+        Some(format!(
+            "must be at most {} characters long",
+            MAX_PASSWORD_LENGTH
+        ))
+    } else if !password.chars().any(char::is_alphabetic)
+        || password.chars().all(char::is_alphabetic)
+    {
+        Some(
+            "must contain letters and at least one digit or symbol"
+                .to_string(),
+        )
+    } else {
+        None
+    }
+}
+
+/// Turn the failed checks, given as field and reason, into a
+/// `ValidationFailed` error listing all of them
+pub fn validate(checks: Vec<(&str, Option<String>)>) -> Result<(), Error> {
+    let errors: Vec<FieldError> = checks
+        .into_iter()
+        .filter_map(|(field, reason)| {
+            reason.map(|reason| FieldError::new(field, &reason))
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::ValidationFailed(errors))
+    }
+}
+
+#[cfg(test)]
+mod account_tests {
+    use handle_errors::{Error, FieldError};
+    use serde::Serialize;
+
+    use super::{
+        Account, AccountId, AccountProfile, LoginRequest, RegisterRequest,
+        Role, Session,
+    };
+
+    #[test]
+    fn session_without_role_is_user() {
+        let session: Session = serde_json::from_str(
+            r#"{
+                "exp": "2023-07-20T00:00:00Z",
+                "nbf": "2023-07-19T00:00:00Z",
+                "account_id": 1
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(session.role, Role::User);
+    }
+
+    #[test]
+    fn role_and_id_cannot_be_set_on_registration() {
+        assert!(serde_json::from_str::<RegisterRequest>(
+            r#"{"email": "a@b.c", "password": "pw", "role": "admin"}"#,
+        )
+        .is_err());
+        assert!(serde_json::from_str::<RegisterRequest>(
+            r#"{"id": 1, "email": "a@b.c", "password": "pw"}"#,
+        )
+        .is_err());
+        assert!(serde_json::from_str::<LoginRequest>(
+            r#"{"id": 1, "email": "a@b.c", "password": "pw"}"#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn registration_lists_every_invalid_field() {
+        let request = RegisterRequest {
+            email: "not an email".to_string(),
+            password: "short".to_string(),
+        };
+        match request.validate() {
+            Err(Error::ValidationFailed(errors)) => assert_eq!(
+                errors,
+                vec![
+                    FieldError::new(
+                        "email",
+                        "must be a valid email address"
+                    ),
+                    FieldError::new(
+                        "password",
+                        "must be at least 8 characters long"
+                    ),
+                ]
+            ),
+            res => panic!("expected a validation error, got {:?}", res),
+        }
+
+        let request = RegisterRequest {
+            email: "test@email.com".to_string(),
+            password: "correct horse 9".to_string(),
+        };
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn profile_does_not_contain_password_hash() {
+        let hash = argon2::hash_encoded(
+            b"correct horse 9",
+            b"somesaltsomesalt",
+            &argon2::Config::default(),
+        )
+        .unwrap();
+        let account = Account {
+            id: AccountId(1),
+            email: "test@email.com".to_string(),
+            password: hash.clone(),
+            role: Role::User,
+            email_verified: false,
+            display_name: None,
+        };
+
+        let json =
+            serde_json::to_string(&AccountProfile::from(account)).unwrap();
+        assert!(!json.contains(&hash));
+        assert!(!json.contains("$argon2"));
+        assert!(!json.contains("password"));
+    }
+
+    /// Fails to compile once `Account`, which holds the password hash,
+    /// implements `Serialize`: both impls of `NotSerialize` would apply
+    /// and the call to `check` becomes ambiguous
+    #[test]
+    fn account_is_not_serializable() {
+        trait NotSerialize<A> {
+            fn check() {}
+        }
+        impl<T: ?Sized> NotSerialize<()> for T {}
+        struct IsSerialize;
+        impl<T: ?Sized + Serialize> NotSerialize<IsSerialize> for T {}
+
+        <Account as NotSerialize<_>>::check();
+    }
+
+    #[test]
+    fn roles_are_ordered() {
+        assert!(Role::User < Role::Moderator);
+        assert!(Role::Moderator < Role::Admin);
+        assert_eq!("moderator".parse::<Role>().unwrap(), Role::Moderator);
+    }
+}
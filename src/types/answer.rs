@@ -12,6 +12,8 @@ pub struct Answer {
     pub content: String,
     /// `QuestionId` of the corresponding `Question`
     pub question_id: QuestionId,
+    /// Sum of the up- and downvotes of the `Answer`
+    pub score: i32,
 }
 
 /// An tuple struct to capture String for the `Answer`'s ID
@@ -26,3 +28,10 @@ pub struct NewAnswer {
     /// `QuestionId` of the corresponding `Question`
     pub question_id: QuestionId,
 }
+
+/// Payload for replacing the content of an existing `Answer`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UpdatedAnswer {
+    /// New String content of the `Answer`
+    pub content: String,
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use super::{answer::AnswerId, question::QuestionId};
+
+/// Comment struct for storing comments on questions and answers
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Comment {
+    /// `CommentId` of the Comment
+    pub id: CommentId,
+    /// String content of the Comment
+    pub content: String,
+    /// `QuestionId` of the commented `Question`, if any
+    pub question_id: Option<QuestionId>,
+    /// `AnswerId` of the commented `Answer`, if any
+    pub answer_id: Option<AnswerId>,
+}
+
+/// Tuple struct for parsing the ID of the `Comment`
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct CommentId(pub i32);
+
+/// Payload for adding or editing a `Comment`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewComment {
+    /// String content of the NewComment
+    pub content: String,
+}
+
+/// The post a comment is attached to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommentTarget {
+    /// Comment on a `Question`
+    Question(QuestionId),
+    /// Comment on an `Answer`
+    Answer(AnswerId),
+}
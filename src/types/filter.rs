@@ -0,0 +1,360 @@
+use std::{collections::HashMap, str::FromStr};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use handle_errors::Error;
+
+use super::{
+    account::AccountId,
+    pagination::{
+        extract_pagination, Cursor, Pagination, DEFAULT_PAGE_SIZE,
+        MAX_PAGE_SIZE,
+    },
+};
+
+/// Order in which the questions are returned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuestionSort {
+    /// Oldest questions first (`sort=oldest`), the default
+    #[default]
+    Oldest,
+    /// Newest questions first (`sort=newest`)
+    Newest,
+    /// Questions with the most answers first (`sort=most_answered`)
+    MostAnswered,
+    /// Questions with the highest score first (`sort=score`)
+    Score,
+}
+
+impl FromStr for QuestionSort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest" => Ok(QuestionSort::Oldest),
+            "newest" => Ok(QuestionSort::Newest),
+            "most_answered" => Ok(QuestionSort::MostAnswered),
+            "score" => Ok(QuestionSort::Score),
+            _ => Err(Error::InvalidParameter(format!("sort={}", s))),
+        }
+    }
+}
+
+/// How multiple `tag` parameters are combined
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TagMatch {
+    /// The question has to carry every tag (`tag_match=all`), the default
+    #[default]
+    All,
+    /// The question has to carry at least one tag (`tag_match=any`)
+    Any,
+}
+
+impl FromStr for TagMatch {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(TagMatch::All),
+            "any" => Ok(TagMatch::Any),
+            _ => Err(Error::InvalidParameter(format!("tag_match={}", s))),
+        }
+    }
+}
+
+/// Filter and sort options for listing questions, extracted
+/// from query params
+#[derive(Debug, Default, PartialEq)]
+pub struct QuestionFilter {
+    /// Tags the questions have to carry
+    pub tags: Vec<String>,
+    /// Whether all or any of the `tags` have to match
+    pub tag_match: TagMatch,
+    /// Only return questions of this account
+    pub author: Option<AccountId>,
+    /// Only return questions created after this point in time
+    pub created_after: Option<NaiveDateTime>,
+    /// Only return questions created before this point in time
+    pub created_before: Option<NaiveDateTime>,
+    /// Only return questions without any answer
+    pub unanswered: bool,
+    /// Order of the returned questions
+    pub sort: QuestionSort,
+    /// Also return deleted questions, only allowed for admins
+    pub include_deleted: bool,
+}
+
+/// Extract filter, sort and pagination parameters from `/questions`
+/// # Example query
+/// `/questions?tag=rust&tag=warp&author=42&unanswered=true&sort=newest`
+///
+/// Passing `cursor` (empty for the first page) switches to keyset
+/// pagination, which cannot be combined with `offset`,
+/// `sort=most_answered` or `sort=score`, and takes a `limit` of at most
+/// `MAX_PAGE_SIZE`. Unknown parameters are rejected instead of being
+/// ignored.
+/// # Example usage
+/// ```rust
+/// use rustwebdev::types::filter::{extract_question_query, QuestionSort};
+///
+/// let params = vec![
+///     ("tag".to_string(), "rust".to_string()),
+///     ("tag".to_string(), "warp".to_string()),
+///     ("sort".to_string(), "newest".to_string()),
+/// ];
+/// let (filter, _) = extract_question_query(params).unwrap();
+/// assert_eq!(filter.tags, vec!["rust", "warp"]);
+/// assert_eq!(filter.sort, QuestionSort::Newest);
+/// ```
+pub fn extract_question_query(
+    params: Vec<(String, String)>,
+) -> Result<(QuestionFilter, Pagination), Error> {
+    let mut filter = QuestionFilter::default();
+    let mut pagination_params = HashMap::new();
+    let mut cursor = None;
+
+    for (key, value) in params {
+        match key.as_str() {
+            "limit" | "offset" => {
+                pagination_params.insert(key, value);
+            }
+            "tag" => filter.tags.push(value.trim().to_lowercase()),
+            "tag_match" => filter.tag_match = value.parse()?,
+            "author" => {
+                filter.author = Some(AccountId(
+                    value.parse::<i32>().map_err(Error::ParseError)?,
+                ))
+            }
+            "created_after" => {
+                filter.created_after = Some(parse_date_time(&key, &value)?)
+            }
+            "created_before" => {
+                filter.created_before =
+                    Some(parse_date_time(&key, &value)?)
+            }
+            "unanswered" => {
+                filter.unanswered =
+                    value.parse::<bool>().map_err(|_| {
+                        Error::InvalidParameter(format!(
+                            "{}={}",
+                            key, value
+                        ))
+                    })?
+            }
+            "sort" => filter.sort = value.parse()?,
+            "include_deleted" => {
+                filter.include_deleted =
+                    value.parse::<bool>().map_err(|_| {
+                        Error::InvalidParameter(format!(
+                            "{}={}",
+                            key, value
+                        ))
+                    })?
+            }
+            "cursor" => cursor = Some(value),
+            _ => return Err(Error::UnknownParameter(key)),
+        }
+    }
+
+    let pagination = match cursor {
+        Some(cursor) => {
+            if pagination_params.contains_key("offset") {
+                return Err(Error::InvalidParameter(
+                    "offset cannot be combined with cursor".to_string(),
+                ));
+            }
+            if matches!(
+                filter.sort,
+                QuestionSort::MostAnswered | QuestionSort::Score
+            ) {
+                return Err(Error::InvalidParameter(
+                    "only sort=oldest|newest can be combined with cursor"
+                        .to_string(),
+                ));
+            }
+            let limit = match pagination_params.get("limit") {
+                Some(limit) => {
+                    limit.parse::<i32>().map_err(Error::ParseError)?
+                }
+                None => DEFAULT_PAGE_SIZE,
+            };
+            if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+                return Err(Error::InvalidParameter(format!(
+                    "limit={}",
+                    limit
+                )));
+            }
+            let after = if cursor.is_empty() {
+                None
+            } else {
+                Some(Cursor::decode(&cursor)?)
+            };
+            Pagination::Cursor { limit, after }
+        }
+        None if pagination_params.is_empty() => Pagination::default(),
+        None => extract_pagination(pagination_params)?,
+    };
+
+    Ok((filter, pagination))
+}
+
+/// Accepts either a RFC 3339 timestamp or a plain `YYYY-MM-DD` date
+fn parse_date_time(
+    key: &str,
+    value: &str,
+) -> Result<NaiveDateTime, Error> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.naive_utc());
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .ok_or_else(|| {
+            Error::InvalidParameter(format!("{}={}", key, value))
+        })
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::{
+        extract_question_query, AccountId, Error, NaiveDate, Pagination,
+        QuestionFilter, QuestionSort, TagMatch, DEFAULT_PAGE_SIZE,
+        MAX_PAGE_SIZE,
+    };
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn empty_query() {
+        let (filter, pagination) =
+            extract_question_query(Vec::new()).unwrap();
+        assert_eq!(filter, QuestionFilter::default());
+        assert_eq!(pagination, Pagination::default());
+    }
+
+    #[test]
+    fn all_parameters() {
+        let (filter, pagination) = extract_question_query(params(&[
+            ("tag", "rust"),
+            ("tag", "warp"),
+            ("tag_match", "any"),
+            ("author", "42"),
+            ("created_after", "2023-05-01"),
+            ("created_before", "2023-05-31T12:00:00Z"),
+            ("unanswered", "true"),
+            ("sort", "most_answered"),
+            ("limit", "10"),
+            ("offset", "20"),
+        ]))
+        .unwrap();
+        let expected = QuestionFilter {
+            tags: vec!["rust".to_string(), "warp".to_string()],
+            tag_match: TagMatch::Any,
+            author: Some(AccountId(42)),
+            created_after: NaiveDate::from_ymd_opt(2023, 5, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0),
+            created_before: NaiveDate::from_ymd_opt(2023, 5, 31)
+                .unwrap()
+                .and_hms_opt(12, 0, 0),
+            unanswered: true,
+            sort: QuestionSort::MostAnswered,
+            include_deleted: false,
+        };
+        assert_eq!(filter, expected);
+        assert_eq!(
+            pagination,
+            Pagination::Offset {
+                limit: Some(10),
+                offset: 20,
+            }
+        );
+    }
+
+    #[test]
+    fn first_cursor_page() {
+        let (_, pagination) =
+            extract_question_query(params(&[("cursor", "")])).unwrap();
+        assert_eq!(
+            pagination,
+            Pagination::Cursor {
+                limit: DEFAULT_PAGE_SIZE,
+                after: None,
+            }
+        );
+    }
+
+    #[test]
+    fn cursor_limit_out_of_range() {
+        let too_large = (MAX_PAGE_SIZE + 1).to_string();
+        for limit in ["0", "-1", too_large.as_str(), "2147483647"] {
+            let result = extract_question_query(params(&[
+                ("cursor", ""),
+                ("limit", limit),
+            ]));
+            assert!(matches!(result, Err(Error::InvalidParameter(_))));
+        }
+
+        let (_, pagination) = extract_question_query(params(&[
+            ("cursor", ""),
+            ("limit", &MAX_PAGE_SIZE.to_string()),
+        ]))
+        .unwrap();
+        assert_eq!(
+            pagination,
+            Pagination::Cursor {
+                limit: MAX_PAGE_SIZE,
+                after: None,
+            }
+        );
+    }
+
+    #[test]
+    fn cursor_with_score_sort() {
+        let result = extract_question_query(params(&[
+            ("cursor", ""),
+            ("sort", "score"),
+        ]));
+        assert!(matches!(result, Err(Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn cursor_with_offset() {
+        let result = extract_question_query(params(&[
+            ("cursor", ""),
+            ("limit", "10"),
+            ("offset", "10"),
+        ]));
+        assert!(matches!(result, Err(Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn unknown_parameter() {
+        let result = extract_question_query(params(&[("page", "2")]));
+        let expected =
+            format!("{}", Error::UnknownParameter("page".into()));
+        assert_eq!(format!("{}", result.unwrap_err()), expected);
+    }
+
+    #[test]
+    fn include_deleted() {
+        let (filter, _) =
+            extract_question_query(params(&[("include_deleted", "true")]))
+                .unwrap();
+        assert!(filter.include_deleted);
+
+        let result =
+            extract_question_query(params(&[("include_deleted", "yes")]));
+        assert!(matches!(result, Err(Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn invalid_sort() {
+        let result = extract_question_query(params(&[("sort", "best")]));
+        assert!(matches!(result, Err(Error::InvalidParameter(_))));
+    }
+}
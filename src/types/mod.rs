@@ -1,8 +1,22 @@
 /// Answer and AnswerID types for processing answers
 pub mod answer;
+/// Comment and CommentId types for comments on questions and answers
+pub mod comment;
+/// QuestionFilter type for filtering and sorting the question list
+pub mod filter;
 /// Pagination type for capturing "start" and "and" from query params
 pub mod pagination;
 /// Question and QuestionID types for processing question
 pub mod question;
+/// Revision types for the edit history of questions and answers
+pub mod revision;
+/// SearchResult type for full-text search over questions
+pub mod search;
+/// Tag type and normalization of question tags
+pub mod tag;
+/// PublicUser type, the public profile of an account
+pub mod user;
+/// Vote types for up- and downvoting questions and answers
+pub mod vote;
 
 pub mod account;
\ No newline at end of file
@@ -1,17 +1,147 @@
 use std::collections::HashMap;
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
 use handle_errors::Error;
+use serde::Serialize;
 
-/// Pagination struct that is getting extracted
+/// Number of items returned per page when a cursor is used without `limit`
+pub const DEFAULT_PAGE_SIZE: i32 = 20;
+/// Largest `limit` accepted together with a cursor
+pub const MAX_PAGE_SIZE: i32 = 100;
+
+/// Pagination enum that is getting extracted
 /// from query params
-#[derive(Debug, Default, PartialEq)]
-pub struct Pagination {
-    /// The index of the last item which has to be returned
+#[derive(Debug, PartialEq)]
+pub enum Pagination {
+    /// `limit`/`offset` based pagination
+    Offset {
+        /// The index of the last item which has to be returned
+        limit: Option<i32>,
+        /// The index of the second item that has to returned
+        offset: i32,
+    },
+    /// Keyset pagination, continuing after the item encoded in `after`
+    Cursor {
+        /// The number of items which have to be returned
+        limit: i32,
+        /// The last item of the previous page, `None` for the first page
+        after: Option<Cursor>,
+    },
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Pagination::Offset {
+            limit: None,
+            offset: 0,
+        }
+    }
+}
+
+impl Pagination {
+    /// Return `limit` and `offset`, for routes which only support
+    /// offset based pagination
+    pub fn into_offset(self) -> Result<(Option<i32>, i32), Error> {
+        match self {
+            Pagination::Offset { limit, offset } => Ok((limit, offset)),
+            Pagination::Cursor { .. } => {
+                Err(Error::InvalidParameter("cursor".to_string()))
+            }
+        }
+    }
+}
+
+/// Position of an item in a list ordered by `(created_on, id)`,
+/// handed to clients as an opaque string
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor {
+    /// Creation time of the item
+    pub created_on: NaiveDateTime,
+    /// ID of the item
+    pub id: i32,
+}
+
+impl Cursor {
+    /// Encode the cursor as an URL safe opaque string
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!(
+            "{}:{}",
+            self.created_on.timestamp_micros(),
+            self.id
+        ))
+    }
+
+    /// Decode a cursor previously created with `Cursor::encode`
+    pub fn decode(cursor: &str) -> Result<Cursor, Error> {
+        let invalid = || Error::InvalidParameter(format!("cursor={}", cursor));
+
+        let decoded = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(invalid)?;
+        let (micros, id) = decoded.split_once(':').ok_or_else(invalid)?;
+
+        Ok(Cursor {
+            created_on: micros
+                .parse::<i64>()
+                .ok()
+                .and_then(NaiveDateTime::from_timestamp_micros)
+                .ok_or_else(invalid)?,
+            id: id.parse::<i32>().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// A page of a `limit`/`offset` paginated list, together with the total
+/// number of items in the list
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    /// The items of the page
+    pub items: Vec<T>,
+    /// The number of items in the whole list
+    pub total: i64,
+    /// The requested `limit`, `None` if the whole list was requested
     pub limit: Option<i32>,
-    /// The index of the second item that has to returned
+    /// The requested `offset`
     pub offset: i32,
 }
 
+/// A page of a cursor paginated list
+#[derive(Debug, Serialize)]
+pub struct CursorPage<T> {
+    /// The items of the page
+    pub items: Vec<T>,
+    /// Cursor for the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Remove the `envelope` flag from the query params. When set, list
+/// routes wrap their items into a `Page` with the total count
+pub fn extract_envelope(
+    params: &mut Vec<(String, String)>,
+) -> Result<bool, handle_errors::Error> {
+    let mut envelope = false;
+    let mut result = Ok(());
+    params.retain(|(key, value)| {
+        if key != "envelope" {
+            return true;
+        }
+        match value.parse::<bool>() {
+            Ok(flag) => envelope = flag,
+            Err(_) => {
+                result = Err(Error::InvalidParameter(format!(
+                    "envelope={}",
+                    value
+                )))
+            }
+        }
+        false
+    });
+    result.map(|_| envelope)
+}
+
 /// Extract query parameter from `/questions` route
 /// # Example query
 /// GET requests to this route can have a pagination attached so we just
@@ -25,14 +155,13 @@ pub struct Pagination {
 /// query.insert("limit".to_string(), "1".to_string());
 /// query.insert("offset".to_string(), "10".to_string());
 /// let p = rustwebdev::types::pagination::extract_pagination(query).unwrap();
-/// assert_eq!(p.limit.unwrap(), 1);
-/// assert_eq!(p.offset, 10);
+/// assert_eq!(p.into_offset().unwrap(), (Some(1), 10));
 /// ```
 pub fn extract_pagination(
     params: HashMap<String, String>,
 ) -> Result<Pagination, handle_errors::Error> {
     if params.contains_key("limit") && params.contains_key("offset") {
-        return Ok(Pagination {
+        return Ok(Pagination::Offset {
             limit: Some(
                 params
                     .get("limit")
@@ -52,9 +181,12 @@ pub fn extract_pagination(
 
 #[cfg(test)]
 mod pagination_tests {
-    use std::num::ParseIntError;
+    use chrono::NaiveDate;
 
-    use super::{extract_pagination, Error, HashMap, Pagination};
+    use super::{
+        extract_envelope, extract_pagination, Cursor, Error, HashMap,
+        Pagination,
+    };
 
     #[test]
     fn valid_paginations() {
@@ -62,7 +194,7 @@ mod pagination_tests {
         params.insert("limit".to_string(), "1".to_string());
         params.insert("offset".to_string(), "1".to_string());
         let pagination_result = extract_pagination(params);
-        let expected = Pagination {
+        let expected = Pagination::Offset {
             limit: Some(1),
             offset: 1,
         };
@@ -77,5 +209,37 @@ mod pagination_tests {
         let expected = format!("{}", Error::MissingParameters);
         assert_eq!(pagination_result, expected);
     }
-    
+    #[test]
+    fn envelope_flag() {
+        let mut params = vec![
+            ("limit".to_string(), "1".to_string()),
+            ("envelope".to_string(), "true".to_string()),
+        ];
+        assert!(extract_envelope(&mut params).unwrap());
+        assert_eq!(params, vec![("limit".to_string(), "1".to_string())]);
+
+        let mut params = vec![("envelope".to_string(), "yes".to_string())];
+        assert!(matches!(
+            extract_envelope(&mut params),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+    #[test]
+    fn cursor_roundtrip() {
+        let cursor = Cursor {
+            created_on: NaiveDate::from_ymd_opt(2023, 5, 7)
+                .unwrap()
+                .and_hms_micro_opt(14, 33, 28, 123_456)
+                .unwrap(),
+            id: 42,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+    }
+    #[test]
+    fn invalid_cursor() {
+        assert!(matches!(
+            Cursor::decode("not a cursor"),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
 }
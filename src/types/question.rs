@@ -1,4 +1,8 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::answer::{Answer, AnswerId};
 
 /// Question struct for storing question data
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -11,6 +15,34 @@ pub struct Question {
     pub content: String,
     /// (Optional) Tags related to the `Question`
     pub tags: Option<Vec<String>>,
+    /// Sum of the up- and downvotes of the `Question`
+    #[serde(default)]
+    pub score: i32,
+    /// (Optional) `AnswerId` of the `Answer` accepted by the author
+    #[serde(default)]
+    pub accepted_answer_id: Option<AnswerId>,
+    /// When the `Question` was deleted, only set for deleted questions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Version of the `Question`, bumped on every edit
+    #[serde(default)]
+    pub version: i32,
+}
+
+/// Whether the value of an `If-Match` header matches `etag`. The header
+/// is either `*` or a comma separated list of entity tags, which are
+/// compared strongly, so weak tags never match
+/// # Example usage
+/// ```rust
+/// use rustwebdev::types::question::if_match_matches;
+///
+/// assert!(if_match_matches("\"a\", \"b\"", "\"b\""));
+/// assert!(if_match_matches("*", "\"b\""));
+/// assert!(!if_match_matches("W/\"b\"", "\"b\""));
+/// ```
+pub fn if_match_matches(value: &str, etag: &str) -> bool {
+    let value = value.trim();
+    value == "*" || value.split(',').any(|tag| tag.trim() == etag)
 }
 
 /// Tuple struct for parsing the ID of the `Question`
@@ -23,3 +55,139 @@ pub struct NewQuestion {
     pub content: String,
     pub tags: Option<Vec<String>>,
 }
+
+/// Payload for replacing an existing `Question`, the `id` is optional
+/// and has to match the path if given
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UpdatedQuestion {
+    /// (Optional) ID of the `Question`, taken from the path
+    #[serde(default)]
+    pub id: Option<QuestionId>,
+    /// New title of the `Question`
+    pub title: String,
+    /// New content of the `Question`
+    pub content: String,
+    /// (Optional) New tags of the `Question`
+    pub tags: Option<Vec<String>>,
+}
+
+/// Payload for partially updating an existing `Question`, only the
+/// given fields are changed. An empty `tags` list removes every tag
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuestionPatch {
+    /// (Optional) New title of the `Question`
+    pub title: Option<String>,
+    /// (Optional) New content of the `Question`
+    pub content: Option<String>,
+    /// (Optional) New tags of the `Question`
+    pub tags: Option<Vec<String>>,
+}
+
+impl QuestionPatch {
+    /// Whether the patch does not change anything
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.content.is_none()
+            && self.tags.is_none()
+    }
+}
+
+/// A single `Question` together with all of its `Answer`s
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuestionWithAnswers {
+    /// The requested `Question`
+    #[serde(flatten)]
+    pub question: Question,
+    /// Every `Answer` given to the `Question`
+    pub answers: Vec<Answer>,
+}
+
+impl QuestionWithAnswers {
+    /// Strong `ETag` derived from a hash of the serialized representation,
+    /// votes, answers and an accepted answer change it like edits do
+    pub fn etag(&self) -> String {
+        let body =
+            serde_json::to_vec(self).expect("question is serializable");
+        format!("\"{:x}\"", Sha256::digest(body))
+    }
+}
+
+#[cfg(test)]
+mod question_tests {
+    use super::{
+        if_match_matches, Question, QuestionId, QuestionPatch,
+        QuestionWithAnswers, UpdatedQuestion,
+    };
+
+    fn question() -> QuestionWithAnswers {
+        QuestionWithAnswers {
+            question: Question {
+                id: QuestionId(1),
+                title: "Title".to_string(),
+                content: "Content".to_string(),
+                tags: None,
+                score: 0,
+                accepted_answer_id: None,
+                deleted_at: None,
+                version: 1,
+            },
+            answers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn etag_changes_without_new_version() {
+        let before = question();
+        let mut after = question();
+        after.question.score = 1;
+        assert_eq!(before.etag(), question().etag());
+        assert_ne!(before.etag(), after.etag());
+    }
+
+    #[test]
+    fn etag_in_list_matches() {
+        let etag = question().etag();
+        let list = format!("\"3\", {}", etag);
+        assert!(if_match_matches(&list, &etag));
+        assert!(if_match_matches(&format!("{},\"3\"", etag), &etag));
+        assert!(!if_match_matches("\"3\", \"4\"", &etag));
+    }
+
+    #[test]
+    fn weak_etag_never_matches() {
+        let etag = question().etag();
+        assert!(!if_match_matches(&format!("W/{}", etag), &etag));
+    }
+
+    #[test]
+    fn unquoted_etag_never_matches() {
+        let etag = question().etag();
+        assert!(!if_match_matches(etag.trim_matches('"'), &etag));
+    }
+
+    #[test]
+    fn patch_with_only_tags() {
+        let patch: QuestionPatch =
+            serde_json::from_str(r#"{"tags": []}"#).unwrap();
+        assert!(patch.title.is_none() && patch.content.is_none());
+        assert_eq!(patch.tags, Some(Vec::new()));
+        assert!(!patch.is_empty());
+        assert!(QuestionPatch::default().is_empty());
+    }
+
+    #[test]
+    fn patch_with_unknown_field() {
+        let patch = serde_json::from_str::<QuestionPatch>(r#"{"id": 1}"#);
+        assert!(patch.is_err());
+    }
+
+    #[test]
+    fn update_without_id() {
+        let question: UpdatedQuestion = serde_json::from_str(
+            r#"{"title": "Title", "content": "Content", "tags": null}"#,
+        )
+        .unwrap();
+        assert!(question.id.is_none());
+    }
+}
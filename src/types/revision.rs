@@ -0,0 +1,38 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use super::{account::AccountId, answer::AnswerId, question::QuestionId};
+
+/// A version of a `Question`, every edit adds a new revision
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuestionRevision {
+    /// `QuestionId` of the revised `Question`
+    pub question_id: QuestionId,
+    /// Number of the revision, starting with 1 for the original version
+    pub revision: i32,
+    /// Title of the `Question` in this revision
+    pub title: String,
+    /// Content of the `Question` in this revision
+    pub content: String,
+    /// (Optional) Tags of the `Question` in this revision
+    pub tags: Option<Vec<String>>,
+    /// `AccountId` of the account which wrote this revision
+    pub editor_id: AccountId,
+    /// When this revision was written
+    pub created_on: NaiveDateTime,
+}
+
+/// A version of an `Answer`, every edit adds a new revision
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnswerRevision {
+    /// `AnswerId` of the revised `Answer`
+    pub answer_id: AnswerId,
+    /// Number of the revision, starting with 1 for the original version
+    pub revision: i32,
+    /// Content of the `Answer` in this revision
+    pub content: String,
+    /// `AccountId` of the account which wrote this revision
+    pub editor_id: AccountId,
+    /// When this revision was written
+    pub created_on: NaiveDateTime,
+}
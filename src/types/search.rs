@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+use super::question::Question;
+
+/// A `Question` matching a full-text search, ordered by `rank`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SearchResult {
+    /// The matching `Question`
+    #[serde(flatten)]
+    pub question: Question,
+    /// Relevance of the `Question` for the search query
+    pub rank: f32,
+    /// Highlighted fragments explaining why the `Question` matched
+    pub snippets: Snippets,
+}
+
+/// Highlighted fragments of a `SearchResult`, matched terms are wrapped
+/// in `<mark>` tags and everything else is HTML escaped
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snippets {
+    /// Highlighted title of the `Question`
+    pub title: String,
+    /// Highlighted fragments of the `Question` content
+    pub content: String,
+    /// Highlighted fragments of the best matching `Answer`, if any
+    pub answer: Option<String>,
+}
@@ -0,0 +1,175 @@
+use handle_errors::Error;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of tags on a single `Question`
+pub const MAX_TAGS: usize = 5;
+/// Maximum length of a single tag
+pub const MAX_TAG_LENGTH: usize = 35;
+
+/// Maximum length of a tag description
+pub const MAX_TAG_DESCRIPTION_LENGTH: usize = 500;
+
+/// Tag struct for listing the known tags
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tag {
+    /// Normalized name of the `Tag`
+    pub name: String,
+    /// (Optional) Description of the `Tag`
+    pub description: Option<String>,
+    /// Alternative spellings which are replaced by the `Tag`
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+    /// Number of questions carrying the `Tag`
+    pub usage_count: i64,
+}
+
+/// Payload for describing a `Tag`, `None` removes the description
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagUpdate {
+    pub description: Option<String>,
+}
+
+/// Payload for merging a tag into another one as its synonym
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewTagSynonym {
+    pub synonym: String,
+}
+
+/// Normalize a single tag like `normalize_tags`, rejecting empty ones
+pub fn normalize_tag(tag: &str) -> Result<String, Error> {
+    match normalize_tags(Some(vec![tag.to_string()]))? {
+        Some(mut tags) if !tags.is_empty() => Ok(tags.remove(0)),
+        _ => Err(Error::InvalidTags("tag must not be empty".to_string())),
+    }
+}
+
+/// Trim a tag description, an empty one removes the description
+pub fn normalize_tag_description(
+    description: Option<String>,
+) -> Result<Option<String>, Error> {
+    let description = match description {
+        Some(description) => description.trim().to_string(),
+        None => return Ok(None),
+    };
+    if description.chars().count() > MAX_TAG_DESCRIPTION_LENGTH {
+        return Err(Error::InvalidParameter(format!(
+            "description is longer than {} characters",
+            MAX_TAG_DESCRIPTION_LENGTH
+        )));
+    }
+    Ok(Some(description).filter(|description| !description.is_empty()))
+}
+
+/// Normalize the tags of a question: trimmed, lowercase, without empty
+/// or duplicate tags. More than `MAX_TAGS` tags or tags longer than
+/// `MAX_TAG_LENGTH` are rejected
+/// # Example usage
+/// ```rust
+/// use rustwebdev::types::tag::normalize_tags;
+///
+/// let tags = vec![" Rust ".to_string(), "rust".to_string(), "".to_string()];
+/// let tags = normalize_tags(Some(tags)).unwrap();
+/// assert_eq!(tags, Some(vec!["rust".to_string()]));
+/// ```
+pub fn normalize_tags(
+    tags: Option<Vec<String>>,
+) -> Result<Option<Vec<String>>, Error> {
+    let tags = match tags {
+        Some(tags) => tags,
+        None => return Ok(None),
+    };
+
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || normalized.contains(&tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(Error::InvalidTags(format!(
+                "tag \"{}\" is longer than {} characters",
+                tag, MAX_TAG_LENGTH
+            )));
+        }
+        normalized.push(tag);
+    }
+
+    if normalized.len() > MAX_TAGS {
+        return Err(Error::InvalidTags(format!(
+            "at most {} tags are allowed",
+            MAX_TAGS
+        )));
+    }
+
+    Ok(Some(normalized))
+}
+
+#[cfg(test)]
+mod tag_tests {
+    use super::{
+        normalize_tag, normalize_tag_description, normalize_tags, Error,
+        MAX_TAGS, MAX_TAG_DESCRIPTION_LENGTH, MAX_TAG_LENGTH,
+    };
+
+    fn tags(tags: &[&str]) -> Option<Vec<String>> {
+        Some(tags.iter().map(|t| t.to_string()).collect())
+    }
+
+    #[test]
+    fn no_tags() {
+        assert_eq!(normalize_tags(None).unwrap(), None);
+    }
+
+    #[test]
+    fn lowercase_trim_and_dedupe() {
+        let result =
+            normalize_tags(tags(&["Rust", " warp ", "rust", " "]));
+        assert_eq!(result.unwrap(), tags(&["rust", "warp"]));
+    }
+
+    #[test]
+    fn too_many_tags() {
+        let many: Vec<String> =
+            (0..=MAX_TAGS).map(|i| format!("tag{}", i)).collect();
+        assert!(matches!(
+            normalize_tags(Some(many)),
+            Err(Error::InvalidTags(_))
+        ));
+    }
+
+    #[test]
+    fn single_tag() {
+        assert_eq!(normalize_tag(" JS ").unwrap(), "js");
+        assert!(matches!(normalize_tag("  "), Err(Error::InvalidTags(_))));
+    }
+
+    #[test]
+    fn tag_description() {
+        assert_eq!(
+            normalize_tag_description(Some(" Systems language ".into()))
+                .unwrap(),
+            Some("Systems language".to_string())
+        );
+        assert_eq!(
+            normalize_tag_description(Some(" ".into())).unwrap(),
+            None
+        );
+        assert!(matches!(
+            normalize_tag_description(Some(
+                "a".repeat(MAX_TAG_DESCRIPTION_LENGTH + 1)
+            )),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn too_long_tag() {
+        let long = "a".repeat(MAX_TAG_LENGTH + 1);
+        assert!(matches!(
+            normalize_tags(tags(&[&long])),
+            Err(Error::InvalidTags(_))
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use chrono::NaiveDateTime;
+use handle_errors::Error;
+use serde::{Deserialize, Serialize};
+
+use super::account::AccountId;
+
+/// Reputation gained per point of score on an own question
+pub const QUESTION_SCORE_REPUTATION: i64 = 5;
+/// Reputation gained per point of score on an own answer
+pub const ANSWER_SCORE_REPUTATION: i64 = 10;
+/// Reputation gained per own answer accepted on someone else's question
+pub const ACCEPTED_ANSWER_REPUTATION: i64 = 15;
+/// Maximum length of a display name
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 64;
+
+/// Public profile of an account. This is the only shape in which other
+/// users get to see an account, it never carries email or password
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PublicUser {
+    /// `AccountId` of the user
+    pub id: AccountId,
+    /// Chosen display name, `user<id>` if none was set
+    pub display_name: String,
+    /// When the account was registered
+    pub joined: NaiveDateTime,
+    /// Number of questions asked, deleted ones excluded
+    pub question_count: i64,
+    /// Number of answers given to questions which are not deleted
+    pub answer_count: i64,
+    /// Reputation earned through votes and accepted answers
+    pub reputation: i64,
+}
+
+/// Payload for changing the display name of the logged in account
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DisplayName {
+    pub display_name: String,
+}
+
+/// Reputation derived from the summed scores of the questions and answers
+/// of a user and the number of their answers accepted by others
+/// # Example usage
+/// ```rust
+/// use rustwebdev::types::user::reputation;
+///
+/// assert_eq!(reputation(0, 0, 0), 0);
+/// assert_eq!(reputation(2, 1, 1), 2 * 5 + 10 + 15);
+/// assert_eq!(reputation(-1, 0, 0), -5);
+/// ```
+pub fn reputation(
+    question_score: i64,
+    answer_score: i64,
+    accepted_answers: i64,
+) -> i64 {
+    question_score * QUESTION_SCORE_REPUTATION
+        + answer_score * ANSWER_SCORE_REPUTATION
+        + accepted_answers * ACCEPTED_ANSWER_REPUTATION
+}
+
+/// Trim a display name and make sure it is neither empty nor longer than
+/// `MAX_DISPLAY_NAME_LENGTH`. Names like `user42` are reserved for the
+/// accounts without a display name
+pub fn normalize_display_name(
+    display_name: &str,
+) -> Result<String, Error> {
+    let display_name = display_name.trim();
+    if display_name.is_empty()
+        || display_name.chars().count() > MAX_DISPLAY_NAME_LENGTH
+        || is_default_display_name(display_name)
+    {
+        return Err(Error::InvalidParameter(format!(
+            "display_name={}",
+            display_name
+        )));
+    }
+    Ok(display_name.to_string())
+}
+
+/// Whether `display_name` has the shape of the `user<id>` default
+fn is_default_display_name(display_name: &str) -> bool {
+    match display_name.to_lowercase().strip_prefix("user") {
+        Some(id) => !id.is_empty() && id.chars().all(char::is_numeric),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod user_tests {
+    use super::{normalize_display_name, Error, MAX_DISPLAY_NAME_LENGTH};
+
+    #[test]
+    fn display_name_is_trimmed() {
+        assert_eq!(normalize_display_name("  Ferris ").unwrap(), "Ferris");
+    }
+
+    #[test]
+    fn default_display_names_are_reserved() {
+        for name in ["user5", " User42 ", "USER007"] {
+            assert!(matches!(
+                normalize_display_name(name),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
+        assert_eq!(normalize_display_name("user").unwrap(), "user");
+        assert_eq!(normalize_display_name("user5a").unwrap(), "user5a");
+    }
+
+    #[test]
+    fn invalid_display_names() {
+        assert!(matches!(
+            normalize_display_name("   "),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            normalize_display_name(
+                &"x".repeat(MAX_DISPLAY_NAME_LENGTH + 1)
+            ),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use super::{answer::AnswerId, question::QuestionId};
+
+/// Direction of a vote
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoteDirection {
+    /// Upvote, adds one to the score
+    Up,
+    /// Downvote, subtracts one from the score
+    Down,
+}
+
+impl VoteDirection {
+    /// Value of the vote as stored in the database
+    pub fn value(self) -> i16 {
+        match self {
+            VoteDirection::Up => 1,
+            VoteDirection::Down => -1,
+        }
+    }
+}
+
+/// NewVote struct that is getting extracted from the request body
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewVote {
+    /// Whether to up- or downvote
+    pub direction: VoteDirection,
+}
+
+/// The post a vote is given to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VoteTarget {
+    /// Vote on a `Question`
+    Question(QuestionId),
+    /// Vote on an `Answer`
+    Answer(AnswerId),
+}
+
+/// Score of a post after a vote has been added or removed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Score {
+    /// Sum of all votes on the post
+    pub score: i32,
+}